@@ -25,7 +25,10 @@ pub struct Create {
     body: Option<String>,
 
     #[builder(default)]
-    #[serde(skip_serializing_if = "Option::is_none", with = "crate::dateformat")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::dateformat::serialize"
+    )]
     published_at: Option<PublishDate>,
 }
 
@@ -54,7 +57,10 @@ pub struct Update {
     body: Option<String>,
 
     #[builder(default)]
-    #[serde(skip_serializing_if = "Option::is_none", with = "crate::dateformat")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::dateformat::serialize"
+    )]
     published_at: Option<PublishDate>,
 }
 
@@ -95,6 +101,17 @@ impl Client {
         action.read(response)
     }
 
+    /// Lists all posts, including their bodies.
+    pub fn all_posts(&self) -> Result<Vec<Post>> {
+        self.call(List {})?
+            .into_iter()
+            .map(|post| match post.body {
+                Some(_) => Ok(post),
+                None => self.call(Get { slug: post.slug }),
+            })
+            .collect()
+    }
+
     pub fn print<A: Action>(&self, action: &A) -> Result<()> {
         let response = self.request(action)?;
         consume_response(response, std::io::stdout().lock())
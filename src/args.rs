@@ -1,15 +1,16 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::PathBuf};
 
+use chrono_tz::Tz;
 use kommandozeile::{
     clap,
-    color_eyre::eyre::{ensure, OptionExt},
+    color_eyre::eyre::{bail, ensure, OptionExt},
     concolor, pkg_name, setup_clap, setup_color_eyre_builder,
     tracing::debug,
     verbosity_filter, Color, Global, InputFile, OutputFile, Result, Verbose,
 };
 use secrecy::{ExposeSecret, SecretString};
 
-use crate::dateformat::PublishDate;
+use crate::{cmark::Wrap, dateformat::Offset, index::GroupBy};
 
 /// mataroa.blog CLI
 #[derive(Debug, clap::Parser)]
@@ -32,6 +33,22 @@ pub struct Args {
     #[clap(flatten)]
     color: Color,
 
+    /// Read the configuration from the given file, instead of
+    /// `<config dir>/mataroa/config.toml`.
+    #[clap(long, env = "MATAROA_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Use the settings of the given profile from the configuration.
+    #[clap(long, env = "MATAROA_PROFILE", global = true)]
+    pub profile: Option<String>,
+
+    /// The time zone of the blog, e.g. "Europe/Berlin", which is used for
+    /// the current date and for relative dates like "tomorrow".
+    /// Defaults to the `timezone` setting in the configuration, or the local
+    /// time zone.
+    #[clap(long, env = "MATAROA_TZ", global = true)]
+    pub tz: Option<Tz>,
+
     /// Don't execute any requests, print their curl equivalent instead.
     #[clap(long, short = 'n')]
     pub dry_run: bool,
@@ -49,7 +66,7 @@ pub struct Args {
 }
 
 #[derive(Clone, Debug, clap::Args)]
-#[group(multiple = false, required = true)]
+#[group(multiple = false, required = false)]
 struct ApiKeys {
     /// Read the API key from the given file.
     #[clap(long = "api-key-file", env = "MATAROA_API_KEY_FILE")]
@@ -112,6 +129,9 @@ impl Args {
                 the_key.trim()
             }
             (None, None, Some(key)) => key.expose_secret(),
+            (None, None, None) => bail!(
+                "An API key is required, give it with --api-key, --api-key-file, or --api-key-cmd"
+            ),
             _ => unreachable!(),
         };
         let secret = SecretString::new(format!("Bearer {api_key}"));
@@ -138,6 +158,11 @@ pub struct Get {
 
     #[clap(flatten)]
     pub print: GetPrint,
+
+    /// Translate links to other posts into wiki-links (`[[Title|label]]`)
+    /// when writing the post with `--output`.
+    #[clap(long, requires = "output")]
+    pub wiki_links: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
@@ -188,8 +213,15 @@ pub struct Update {
     /// The published_at date of the post to update.
     /// If not provided, the published_at date will be read from the frontmatter of the file.
     /// If missing, the published_at date will not be updated.
-    #[clap(long, short, value_parser = crate::dateformat::parse)]
-    pub published_at: Option<PublishDate>,
+    /// Relative dates like "tomorrow" are relative to the current date in the
+    /// time zone of the blog.
+    #[clap(long, short, value_name = "DATE")]
+    pub published_at: Option<String>,
+
+    /// Don't update the post if its new slug is already used by another
+    /// post, instead of only warning about it.
+    #[clap(long)]
+    pub abort_on_collision: bool,
 }
 
 /// Delete a post
@@ -205,9 +237,11 @@ pub struct Delete {
 #[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
 #[clap(visible_alias = "new")]
 pub struct Create {
-    /// The file to update
-    #[clap()]
-    pub body: InputFile,
+    /// The file to update.
+    /// With --template, the file to write, which is named after the slug of
+    /// the title by default.
+    #[clap(required_unless_present = "template")]
+    pub body: Option<InputFile>,
 
     /// The title of the post to update.
     /// If not provided, the title will be read from the frontmatter of the file.
@@ -218,8 +252,36 @@ pub struct Create {
     /// The published_at date of the post to update.
     /// If not provided, the published_at date will be read from the frontmatter of the file.
     /// If missing, the published_at date will not be updated.
-    #[clap(long, short, value_parser = crate::dateformat::parse)]
-    pub published_at: Option<PublishDate>,
+    /// Relative dates like "tomorrow" are relative to the current date in the
+    /// time zone of the blog.
+    #[clap(long, short, value_name = "DATE")]
+    pub published_at: Option<String>,
+
+    /// Don't write the slug, `published_at` date, and URL of the created post
+    /// back into the frontmatter of the file.
+    #[clap(long)]
+    pub no_write_back: bool,
+
+    /// Don't create the post if its predicted slug is already used by
+    /// another post, instead of only warning about it.
+    #[clap(long)]
+    pub abort_on_collision: bool,
+
+    /// Write a new post file from this template instead of creating a post.
+    ///
+    /// The template is read from `templates/<TEMPLATE>.md` in the config
+    /// directory. Its template expressions are expanded, with the variables
+    /// `today`, `date` (the `published_at` date), and `count` (one more than
+    /// the highest number in the titles of existing posts that match the title
+    /// of the template, e.g. "Weekly Notes #{{ count }}").
+    /// A date expression as `published_at` is replaced by the date.
+    #[clap(long)]
+    pub template: Option<String>,
+
+    /// Open the new post file in $VISUAL or $EDITOR and create the post when
+    /// the editor exits successfully.
+    #[clap(long, requires = "template")]
+    pub edit: bool,
 }
 
 /// Run multiple commands in a row.
@@ -250,6 +312,589 @@ pub struct Batch {
     pub ignore_errors: bool,
 }
 
+/// Render a post as a local HTML page
+///
+/// The post can be given as a file, in the same format that is used by the
+/// `create` and `update` commands, or as the slug of an existing post.
+/// If a file with the given name exists, it is used, otherwise the argument
+/// is treated as a slug.
+///
+/// With --serve, a local HTTP server is started instead, which renders post
+/// files on every request and reloads the page in the browser whenever one
+/// of the files changes.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Preview {
+    /// The post file to render, or the slug of an existing post.
+    /// With --serve, a post file or a directory of post files.
+    #[clap()]
+    pub source: String,
+
+    /// Write the HTML page to the given file instead of stdout.
+    ///
+    /// The file will be overwritten if it already exists.
+    #[clap(long, short, conflicts_with = "serve")]
+    pub output: Option<OutputFile>,
+
+    /// Start a local server that renders and live-reloads the post files.
+    #[clap(long, short)]
+    pub serve: bool,
+
+    /// The address the preview server listens on.
+    #[clap(long, default_value = "127.0.0.1:4000", requires = "serve")]
+    pub addr: String,
+}
+
+/// Predict the slug of a new post
+///
+/// mataroa generates the slug of a post from its title when it is created.
+/// This command predicts that slug and checks whether it is already used by
+/// another post, in which case mataroa would add a random suffix to it.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Slug {
+    /// The title of the post
+    #[clap()]
+    pub title: String,
+}
+
+/// Change the slug of a post and rewrite all links to it
+///
+/// Changes the slug of the post and then updates every post that links to
+/// the old slug to link to the new one instead.
+/// Links relative to the blog (/blog/<slug>/) and absolute links to the
+/// domain of the blog are rewritten; additional domains (e.g. a custom
+/// domain) can be added with --domain.
+///
+/// The planned rewrites are printed before they are applied.
+/// With --dry-run, nothing is changed.
+/// If any of the updates fails, all previous changes are reverted.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Rename {
+    /// The current slug of the post
+    #[clap()]
+    pub old: String,
+
+    /// The new slug of the post
+    #[clap()]
+    pub new: String,
+
+    /// Also rewrite absolute links to this domain.
+    /// Can be used multiple times.
+    #[clap(long)]
+    pub domain: Vec<String>,
+}
+
+/// Check the links between posts
+///
+/// Finds all links in all posts that point to a post of the blog itself and
+/// reports links to posts that don't exist, links to drafts, and links to
+/// posts that are scheduled to be published after the post that contains
+/// the link.
+/// Links relative to the blog (/blog/<slug>/) and absolute links to the
+/// domain of the blog are checked; additional domains (e.g. a custom
+/// domain) can be added with --domain.
+///
+/// The command fails if any problems were found.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct CheckLinks {
+    /// Also check absolute links to this domain.
+    /// Can be used multiple times.
+    #[clap(long)]
+    pub domain: Vec<String>,
+}
+
+/// Export the links between posts as a graph
+///
+/// Builds the graph of all links between the posts of the blog and prints
+/// it in the given format.
+///
+/// With --orphans, only the slugs of the posts that no other post links to
+/// are printed.
+///
+/// With --backlinks, a "Linked from" section is added to the end of every
+/// post that other posts link to, instead of printing anything.
+/// The section is delimited by markers, so that running the command again
+/// refreshes the section instead of adding another one.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Graph {
+    /// The format of the graph.
+    #[clap(long, short, value_enum, default_value_t = GraphFormat::Dot)]
+    pub format: GraphFormat,
+
+    /// Write the graph to the given file instead of stdout.
+    #[clap(long, short)]
+    pub output: Option<OutputFile>,
+
+    /// Only print the posts that no other post links to.
+    #[clap(long, conflicts_with = "backlinks")]
+    pub orphans: bool,
+
+    /// Add or refresh the "Linked from" section of every post.
+    #[clap(long, conflicts_with_all = ["format", "output"])]
+    pub backlinks: bool,
+
+    /// Also consider absolute links to this domain.
+    /// Can be used multiple times.
+    #[clap(long)]
+    pub domain: Vec<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// JSON with a list of nodes and a list of edges
+    Json,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
+/// Watch post files and push every saved change
+///
+/// Watches a post file or a directory of post files (recursively).
+/// Whenever a file is saved, it is parsed the same way as for the `update`
+/// command and, if the content has changed, pushed to mataroa.
+/// Files that have a slug update the existing post.
+/// Files without a slug create a new post, and the slug that mataroa assigned
+/// to it is written back into the frontmatter of the file.
+///
+/// Pushes that fail because of network or server errors are retried with an
+/// increasing delay.
+/// The command runs until it is interrupted.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Watch {
+    /// A post file or a directory of post files
+    #[clap()]
+    pub path: PathBuf,
+
+    /// Wait for this many milliseconds after a save before pushing,
+    /// so that multiple saves in quick succession result in a single push.
+    #[clap(long, default_value_t = 500)]
+    pub debounce: u64,
+}
+
+/// Format post files in place
+///
+/// The transforms that are enabled in the config, the selected profile, or
+/// the frontmatter of a file are applied to the body of the file, plus
+/// those that are given with --transform.
+/// Then the whole file is written in a canonical form: frontmatter keys are
+/// ordered and quoted, trailing whitespace is removed, list markers, emphasis,
+/// and headings use one style, and reference definitions are gathered at the end.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Fmt {
+    /// The post files to format
+    #[clap(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Don't write the files, but fail if any of them is not formatted
+    #[clap(long)]
+    pub check: bool,
+
+    /// How to wrap paragraphs: `keep` the line breaks, one `sentence` per line,
+    /// or fill lines up to a width.
+    /// Defaults to the `fmt.wrap` setting in the config, or `keep`.
+    #[clap(long, value_name = "keep|sentence|WIDTH")]
+    pub wrap: Option<Wrap>,
+
+    /// Also apply this transform.
+    /// Can be used multiple times.
+    #[clap(long, short, value_enum)]
+    pub transform: Vec<Transform>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Transform {
+    /// Shift all headings so that the highest one is an h2
+    ShiftHeadings,
+    /// Generate a table of contents at the `<!-- toc -->` marker
+    Toc,
+    /// Number the footnotes in the order they are referenced and move
+    /// their definitions to the end
+    Footnotes,
+    /// Use typographic quotes, dashes, and ellipses
+    Typography,
+}
+
+/// Post a short note
+///
+/// The note is published today. Its title and slug are generated from the
+/// `note.title` and `note.slug` patterns in the config, by default the first
+/// sentence of the text and the current date and time.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Note {
+    /// The text of the note.
+    /// Multiple arguments are joined with spaces.
+    /// If there are none, the text is read from stdin.
+    #[clap()]
+    pub text: Vec<String>,
+
+    /// Append the note with the current time to today's daily post instead
+    /// of creating a new post.
+    /// The daily post is created if it doesn't exist yet.
+    #[clap(long)]
+    pub append_to_daily: bool,
+}
+
+/// Show the editorial calendar and schedule drafts
+///
+/// Prints the published and scheduled posts around today, together with the
+/// free slots of the publishing cadence, which is configured as a list of
+/// weekdays in `schedule.cadence` in the config.
+/// A slot is free if no post is published or scheduled on that day.
+///
+/// With --fill, the given drafts are scheduled into the next free slots after
+/// today, in the given order.
+/// The plan is printed before the posts are updated.
+///
+/// With --at, a single draft is published at a specific time of day instead.
+/// mataroa only knows the date of a post, so the job is recorded in a local
+/// queue and the post is published by `roa scheduler run` when it is due.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Schedule {
+    /// Schedule the given drafts into the next free slots.
+    #[clap(long, requires = "drafts")]
+    pub fill: bool,
+
+    /// Publish the given draft at this date and time,
+    /// e.g. "2026-11-02 09:00 Europe/Berlin".
+    /// Without a time zone, the local time zone is used.
+    #[clap(
+        long,
+        value_name = "DATETIME",
+        conflicts_with = "fill",
+        requires = "drafts"
+    )]
+    pub at: Option<String>,
+
+    /// The slugs of the drafts to schedule with --fill, in order,
+    /// or the slug of the draft to publish with --at.
+    #[clap()]
+    pub drafts: Vec<String>,
+
+    /// Show this many weeks before and after today.
+    #[clap(long, default_value_t = 4)]
+    pub weeks: u32,
+}
+
+/// Export the publishing plan as a calendar
+///
+/// Lists every published and scheduled post on its publish date.
+/// Drafts are omitted, since they don't have a date.
+///
+/// With --ics, an iCalendar file is written instead, with one all-day event
+/// per post, which calendar apps can import or subscribe to.
+/// Every event has a stable UID that is derived from the slug of the post,
+/// so that importing the file again updates the events instead of adding
+/// new ones.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Calendar {
+    /// Write an iCalendar (.ics) file.
+    #[clap(long)]
+    pub ics: bool,
+
+    /// Write the calendar to the given file instead of stdout.
+    ///
+    /// The file will be overwritten if it already exists.
+    #[clap(long, short)]
+    pub output: Option<OutputFile>,
+}
+
+/// Run and manage the local publish scheduler
+///
+/// Jobs are added with `roa schedule --at <DATETIME> <SLUG>` and kept in a
+/// queue file, by default `<data dir>/mataroa/jobs.json`, or the
+/// `scheduler.queue` setting in the config.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Scheduler {
+    #[clap(subcommand)]
+    pub cmd: SchedulerCommand,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, clap::Subcommand)]
+pub enum SchedulerCommand {
+    Run(SchedulerRun),
+    List(SchedulerList),
+    Cancel(SchedulerCancel),
+}
+
+/// Publish the drafts of the jobs when they are due
+///
+/// Runs until it is interrupted, and publishes every draft by setting its
+/// publish date to today once the time of its job has come.
+/// Jobs that were missed while the scheduler was not running are run right
+/// away, and failed jobs are retried after a minute.
+///
+/// With --due, only the jobs that are due now are run, and the command exits,
+/// which is meant to be called regularly from cron or a systemd timer.
+/// With --dry-run, the due jobs are printed and kept in the queue.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct SchedulerRun {
+    /// Run the jobs that are due now and exit.
+    #[clap(long)]
+    pub due: bool,
+}
+
+/// List the queued jobs
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct SchedulerList {}
+
+/// Cancel a queued job
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct SchedulerCancel {
+    /// The id of the job, or the slug of its post
+    #[clap()]
+    pub job: String,
+}
+
+/// Manage series of posts
+///
+/// A post belongs to a series if its post file has a `series` with the `name`
+/// of the series and the `order` of the post in it in the frontmatter:
+///
+///     series:
+///       name: Rust Tips
+///       order: 2
+///
+/// This is stored in a comment in the body of the post when it is published,
+/// so that the parts of a series can be found among the posts of the blog.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Series {
+    #[clap(subcommand)]
+    pub cmd: SeriesCommand,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, clap::Subcommand)]
+pub enum SeriesCommand {
+    List(SeriesList),
+    Reorder(SeriesReorder),
+    Schedule(SeriesSchedule),
+    Nav(SeriesNav),
+}
+
+/// List the series, or the parts of a series
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct SeriesList {
+    /// The name of the series to list the parts of
+    #[clap()]
+    pub name: Option<String>,
+}
+
+/// Change the order of the parts of a series
+///
+/// The given parts come first, in the given order, followed by the other
+/// parts in their current order.
+/// The navigation of all parts is refreshed.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct SeriesReorder {
+    /// The name of the series
+    #[clap()]
+    pub name: String,
+
+    /// The slugs of the parts, in their new order
+    #[clap(required = true)]
+    pub slugs: Vec<String>,
+}
+
+/// Schedule the remaining parts of a series at an interval
+///
+/// The parts that are drafts are scheduled in order, one interval apart.
+/// The plan is printed before the posts are updated.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct SeriesSchedule {
+    /// The name of the series
+    #[clap()]
+    pub name: String,
+
+    /// The time between two parts, e.g. 3d, 1w, or 1m.
+    #[clap(long, value_name = "INTERVAL")]
+    pub every: Offset,
+
+    /// The date of the first scheduled part, which can be any date expression.
+    /// Defaults to one interval after the last part that has a date, or
+    /// after today.
+    #[clap(long, value_name = "DATE")]
+    pub start: Option<String>,
+}
+
+/// Add or refresh the navigation of the parts of a series
+///
+/// Adds a "Part N of M" header to the start of every part and links to the
+/// previous and next part to the end.
+/// Both are delimited by markers, so that running the command again
+/// refreshes them instead of adding them again.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct SeriesNav {
+    /// The name of the series, or all series if none is given
+    #[clap()]
+    pub name: Option<String>,
+}
+
+/// Show the date that a date expression resolves to
+///
+/// Resolves the expression the same way as the --published-at option of
+/// `create` and `update`, to check it before using it.
+/// Besides dates like "2024-05-17", "17 may", or "in 3 days", there are
+/// expressions that are relative to today, like "tomorrow", "+3d", "-2w",
+/// "+1m", "next tuesday", "end of month", or "start of next week", and
+/// expressions that are relative to the date of another post, like
+/// "same as <slug>", "2 weeks after <slug>", or "3 days before <slug>".
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Date {
+    /// The date expression.
+    /// Multiple arguments are joined with spaces.
+    #[clap(required = true)]
+    pub expr: Vec<String>,
+}
+
+/// Split a long post file into a series of part files
+///
+/// The body is split before every heading of the given level or higher,
+/// and the text before the first of them stays with the first part.
+/// Each part is written next to the file as `<name>-part-<n>.md`, with
+/// the title "<title> (Part <n>)", the frontmatter of the file, the series
+/// that the parts form, and links to the previous and next part.
+/// The parts are drafts, unless --every is given, and can then be created
+/// in order.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Split {
+    /// The post file to split
+    #[clap()]
+    pub file: PathBuf,
+
+    /// Split before headings of this level or higher
+    #[clap(long, value_enum, default_value = "h2")]
+    pub at: Heading,
+
+    /// Schedule the parts one interval apart, e.g. 3d, 1w, or 1m.
+    #[clap(long, value_name = "INTERVAL")]
+    pub every: Option<Offset>,
+
+    /// The date of the first part, which can be any date expression.
+    /// Defaults to the date of the file, or to one interval after today.
+    #[clap(long, value_name = "DATE", requires = "every")]
+    pub start: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Heading {
+    H1,
+    H2,
+    H3,
+    H4,
+    H5,
+    H6,
+}
+
+/// Update the index post with a list of all published posts
+///
+/// The list is grouped by year or by month, newest first, and rendered
+/// with the templates of the `[index]` section in the config.
+/// It is written between markers in the body of the index post, so that
+/// the rest of the body is kept, and the post is only updated when the
+/// list has changed.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Index {
+    /// The slug of the index post.
+    /// Defaults to the `index.slug` setting in the config.
+    #[clap(long)]
+    pub slug: Option<String>,
+
+    /// Group the posts by year or by month.
+    /// Defaults to the `index.group_by` setting in the config, or month.
+    #[clap(long, value_enum)]
+    pub group_by: Option<GroupBy>,
+}
+
+/// Apply the retention policies to the posts
+///
+/// Posts whose `expires` date has come are reverted to drafts or deleted,
+/// and drafts that haven't changed for a while are deleted, as set in the
+/// `[expire]` section of the config or the selected profile.
+/// Deleted posts are backed up to local files first.
+/// Without --apply, the changes are only shown.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Expire {
+    /// Make the changes instead of only showing them
+    #[clap(long)]
+    pub apply: bool,
+}
+
+/// Publish posts today
+///
+/// Drafts and scheduled posts are published today, posts that are already
+/// published keep their date.
+/// The date of every selected post is shown before and after the change,
+/// and the changes are made after they are confirmed.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Publish {
+    #[clap(flatten)]
+    pub select: Select,
+
+    /// Don't ask for confirmation
+    #[clap(long, short)]
+    pub yes: bool,
+}
+
+/// Turn posts back into drafts
+///
+/// The date of every selected post is shown before and after the change,
+/// and the changes are made after they are confirmed.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Unpublish {
+    #[clap(flatten)]
+    pub select: Select,
+
+    /// Don't ask for confirmation
+    #[clap(long, short)]
+    pub yes: bool,
+}
+
+/// Move posts to another date
+///
+/// The date of every selected post is shown before and after the change,
+/// and the changes are made after they are confirmed.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+pub struct Reschedule {
+    #[clap(flatten)]
+    pub select: Select,
+
+    /// The new date, which can be any date expression, e.g. "next monday"
+    /// or "2 weeks after <slug>".
+    #[clap(long, value_name = "DATE")]
+    pub to: String,
+
+    /// Don't ask for confirmation
+    #[clap(long, short)]
+    pub yes: bool,
+}
+
+/// The posts that a command works on.
+/// A post is selected if it matches all of the given selectors.
+#[derive(Clone, Debug, PartialEq, Eq, clap::Args)]
+#[group(required = true, multiple = true)]
+pub struct Select {
+    /// The slugs of the posts, which can use `*` as a wildcard,
+    /// e.g. `weekly-*`
+    #[clap()]
+    pub slugs: Vec<String>,
+
+    /// Select the drafts
+    #[clap(long)]
+    pub drafts: bool,
+
+    /// Select the posts that are scheduled after today
+    #[clap(long)]
+    pub scheduled: bool,
+
+    /// Select the posts that are published
+    #[clap(long)]
+    pub published: bool,
+
+    /// Select the parts of this series
+    #[clap(long, value_name = "NAME")]
+    pub series: Option<String>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, clap::Subcommand)]
 pub enum Command {
     List(List),
@@ -258,6 +903,25 @@ pub enum Command {
     Update(Update),
     Delete(Delete),
     Batch(Batch),
+    Preview(Preview),
+    Watch(Watch),
+    Slug(Slug),
+    Rename(Rename),
+    CheckLinks(CheckLinks),
+    Graph(Graph),
+    Fmt(Fmt),
+    Note(Note),
+    Schedule(Schedule),
+    Calendar(Calendar),
+    Scheduler(Scheduler),
+    Date(Date),
+    Series(Series),
+    Split(Split),
+    Index(Index),
+    Expire(Expire),
+    Publish(Publish),
+    Unpublish(Unpublish),
+    Reschedule(Reschedule),
 }
 
 const fn short_version() -> &'static str {
@@ -0,0 +1,153 @@
+use std::ops::Range;
+
+// Generated content in a post body is delimited by HTML comments (which are
+// not rendered), so that it can be found and replaced when it is generated
+// again:
+//
+// <!-- roa:backlinks -->
+// generated content
+// <!-- /roa:backlinks -->
+
+// Data that is kept with a post, but not shown, is stored as JSON in a
+// single comment:
+//
+// <!-- roa:series {"name":"Rust","order":2} -->
+
+fn data_marker(name: &str) -> String {
+    format!("<!-- roa:{name} {{")
+}
+
+fn start_marker(name: &str) -> String {
+    format!("<!-- roa:{name} -->")
+}
+
+fn end_marker(name: &str) -> String {
+    format!("<!-- /roa:{name} -->")
+}
+
+/// The range of the block `name` in `body`, including its markers.
+pub fn find(body: &str, name: &str) -> Option<Range<usize>> {
+    let start_marker = start_marker(name);
+    let end_marker = end_marker(name);
+    let start = body.find(&start_marker)?;
+    let end = body[start..].find(&end_marker)? + start + end_marker.len();
+    Some(start..end)
+}
+
+/// The content of the block `name` in `body`, without its markers.
+pub fn content<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    let range = find(body, name)?;
+    let block = &body[range];
+    let content = &block[start_marker(name).len()..block.len() - end_marker(name).len()];
+    Some(content.trim_matches('\n'))
+}
+
+/// Removes the block `name` from `body`, if there is one.
+pub fn remove(body: &str, name: &str) -> String {
+    let Some(range) = find(body, name) else {
+        return body.to_owned();
+    };
+
+    let head = body[..range.start].trim_end();
+    let tail = body[range.end..].trim_start();
+    match (head.is_empty(), tail.is_empty()) {
+        (true, _) => tail.to_owned(),
+        (_, true) => head.to_owned(),
+        _ => format!("{head}\n\n{tail}"),
+    }
+}
+
+/// Replaces the block `name` in `body` with `content`.
+///
+/// If there is no such block yet, it is added at the end of the body.
+/// An empty `content` removes the block.
+pub fn replace(body: &str, name: &str, content: &str) -> String {
+    put(body, name, content, false)
+}
+
+/// Like [`replace`], but a new block is added at the start of the body.
+pub fn replace_at_start(body: &str, name: &str, content: &str) -> String {
+    put(body, name, content, true)
+}
+
+/// The JSON of the data `name` in `body`, and the range of its comment.
+fn find_data<'a>(body: &'a str, name: &str) -> Option<(Range<usize>, &'a str)> {
+    let start = body.find(&data_marker(name))?;
+    // the marker ends with the opening brace of the JSON object
+    let json_start = start + data_marker(name).len() - 1;
+    let json_end = body[json_start..].find(" -->")? + json_start;
+    Some((start..json_end + " -->".len(), &body[json_start..json_end]))
+}
+
+/// The JSON of the data `name` in `body`, if there is any.
+pub fn data<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    find_data(body, name).map(|(_, json)| json)
+}
+
+/// Replaces the data `name` in `body` with the JSON object `json`.
+/// If there is no such data yet, it is added at the end of the body.
+pub fn set_data(body: &str, name: &str, json: &str) -> String {
+    // the JSON must not end the comment
+    let comment = format!("<!-- roa:{name} {} -->", json.replace("-->", "--\\u003e"));
+
+    if let Some((range, _)) = find_data(body, name) {
+        let mut body = body.to_owned();
+        body.replace_range(range, &comment);
+        return body;
+    }
+
+    let body = body.trim_matches('\n');
+    if body.is_empty() {
+        return comment;
+    }
+    format!("{body}\n\n{comment}")
+}
+
+fn put(body: &str, name: &str, content: &str, at_start: bool) -> String {
+    let content = content.trim_matches('\n');
+    if content.is_empty() {
+        return remove(body, name);
+    }
+
+    let block = format!("{}\n{content}\n{}", start_marker(name), end_marker(name));
+
+    if let Some(range) = find(body, name) {
+        let mut body = body.to_owned();
+        body.replace_range(range, &block);
+        return body;
+    }
+
+    let body = body.trim_matches('\n');
+    if body.is_empty() {
+        return block;
+    }
+
+    if at_start {
+        format!("{block}\n\n{body}")
+    } else {
+        format!("{body}\n\n{block}")
+    }
+}
+
+#[test]
+fn replace_blocks() {
+    let body = replace("Body", "x", "one");
+    assert_eq!(body, "Body\n\n<!-- roa:x -->\none\n<!-- /roa:x -->");
+
+    let body = replace(&body, "x", "two");
+    assert_eq!(body, "Body\n\n<!-- roa:x -->\ntwo\n<!-- /roa:x -->");
+
+    let body = replace(&body, "y", "three");
+    let body = replace(&body, "x", "");
+    assert_eq!(body, "Body\n\n<!-- roa:y -->\nthree\n<!-- /roa:y -->");
+    assert_eq!(remove(&body, "y"), "Body");
+
+    let body = replace_at_start("Body", "x", "one");
+    assert_eq!(body, "<!-- roa:x -->\none\n<!-- /roa:x -->\n\nBody");
+
+    let body = set_data(&body, "x", r#"{"a":"-->"}"#);
+    assert_eq!(data(&body, "x"), Some(r#"{"a":"--\u003e"}"#));
+    let body = set_data(&body, "x", "{}");
+    assert!(body.ends_with("Body\n\n<!-- roa:x {} -->"));
+    assert_eq!(find(&body, "x"), Some(0..34));
+}
@@ -0,0 +1,211 @@
+use std::fmt::Write as _;
+
+use chrono::{Days, NaiveDate as Date, NaiveDateTime};
+use kommandozeile::{OutputFile, Result};
+
+use crate::{
+    api, args,
+    config::Config,
+    dateformat::{self, PublishDate},
+    write_output, Post,
+};
+
+/// Lines of an iCalendar file are folded after this many octets.
+const MAX_LINE: usize = 75;
+
+/// Prints the publish dates of all posts, or writes them as an iCalendar file.
+pub fn run(client: &api::Client, config: &Config, cmd: args::Calendar) -> Result<()> {
+    let posts = client.call(api::List {})?;
+    let mut dated = posts
+        .iter()
+        .filter_map(|post| match post.publish_date() {
+            PublishDate::Date(date) => Some((date, post)),
+            PublishDate::Draft => None,
+        })
+        .collect::<Vec<_>>();
+    dated.sort_by(|(a, p), (b, q)| a.cmp(b).then_with(|| p.slug.cmp(&q.slug)));
+
+    let today = dateformat::today(config.timezone);
+    let output = cmd.output.unwrap_or(OutputFile::Stdout(None));
+    if cmd.ics {
+        let now = chrono::Utc::now().naive_utc();
+        return write_output(output, ics(&dated, today, now));
+    }
+
+    let mut out = String::new();
+    for (date, post) in dated {
+        let state = if date > today {
+            "scheduled"
+        } else {
+            "published"
+        };
+        let _ = write!(
+            out,
+            "{}  {state:<9}  {}",
+            date.format("%Y-%m-%d"),
+            post.slug
+        );
+        if let Some(title) = &post.title {
+            let _ = write!(out, ": {title}");
+        }
+        out.push('\n');
+    }
+    write_output(output, out)
+}
+
+/// An RFC 5545 calendar with an all-day event for every post.
+fn ics(posts: &[(Date, &Post)], today: Date, now: NaiveDateTime) -> String {
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        format!(
+            "PRODID:-//{}//{}//EN",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ),
+        String::from("CALSCALE:GREGORIAN"),
+        String::from("METHOD:PUBLISH"),
+    ];
+
+    for &(date, post) in posts {
+        let scheduled = date > today;
+        let title = post.title.as_deref().unwrap_or(&post.slug);
+        let summary = if scheduled {
+            format!("Scheduled: {title}")
+        } else {
+            title.to_owned()
+        };
+
+        lines.push(String::from("BEGIN:VEVENT"));
+        lines.push(format!("UID:{}@{}", post.slug, host(post)));
+        lines.push(format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+        let end = date + Days::new(1);
+        lines.push(format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")));
+        lines.push(format!("SUMMARY:{}", escape(&summary)));
+        if let Some(url) = &post.url {
+            lines.push(format!("URL:{url}"));
+            lines.push(format!("DESCRIPTION:{}", escape(url)));
+        }
+        let (status, category) = if scheduled {
+            ("TENTATIVE", "scheduled")
+        } else {
+            ("CONFIRMED", "published")
+        };
+        lines.push(format!("STATUS:{status}"));
+        lines.push(format!("CATEGORIES:{category}"));
+        lines.push(String::from("TRANSP:TRANSPARENT"));
+        lines.push(String::from("END:VEVENT"));
+    }
+
+    lines.push(String::from("END:VCALENDAR"));
+
+    let mut out = String::new();
+    for line in lines {
+        fold(&line, &mut out);
+    }
+    out
+}
+
+/// The host of the blog, taken from the URL of the post, which makes the
+/// UIDs of the events unique across blogs.
+fn host(post: &Post) -> &str {
+    post.url
+        .as_deref()
+        .and_then(|url| url.split_once("://"))
+        .and_then(|(_, rest)| rest.split('/').next())
+        .filter(|host| !host.is_empty())
+        .unwrap_or("mataroa.blog")
+}
+
+/// Escapes a TEXT value.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a content line, folded into lines of at most 75 octets that are
+/// terminated by CRLF.
+fn fold(line: &str, out: &mut String) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > MAX_LINE {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[test]
+fn ics_events() {
+    let post = |slug: &str, title: &str| Post {
+        slug: slug.to_owned(),
+        title: Some(title.to_owned()),
+        body: None,
+        published_at: None,
+        url: Some(format!("https://example.mataroa.blog/blog/{slug}/")),
+    };
+    let date = |d| Date::from_ymd_opt(2015, 10, d).unwrap();
+    let old = post("old", "Old");
+    let new = post("new", "New");
+    let now = date(21).and_hms_opt(16, 29, 0).unwrap();
+
+    let ics = ics(&[(date(20), &old), (date(22), &new)], date(21), now);
+    assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+    assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+    assert!(ics.contains("UID:old@example.mataroa.blog\r\n"));
+    assert!(ics.contains("DTSTAMP:20151021T162900Z\r\n"));
+    assert!(ics.contains("DTSTART;VALUE=DATE:20151020\r\nDTEND;VALUE=DATE:20151021\r\n"));
+    assert!(ics.contains("SUMMARY:Old\r\n"));
+    assert!(ics.contains("SUMMARY:Scheduled: New\r\n"));
+    assert!(ics.contains("STATUS:CONFIRMED\r\n"));
+    assert!(ics.contains("STATUS:TENTATIVE\r\n"));
+}
+
+#[test]
+fn ics_escapes_text() {
+    let post = Post {
+        slug: String::from("post"),
+        title: Some(String::from("Old, but; gold")),
+        body: None,
+        published_at: None,
+        url: Some(String::from("https://example.mataroa.blog/blog/post/")),
+    };
+    let date = |d| Date::from_ymd_opt(2015, 10, d).unwrap();
+    let now = date(21).and_hms_opt(16, 29, 0).unwrap();
+
+    let ics = ics(&[(date(20), &post)], date(21), now);
+    assert!(ics.contains("SUMMARY:Old\\, but\\; gold\r\n"));
+}
+
+#[test]
+fn ics_folds_long_lines() {
+    let post = Post {
+        slug: String::from("post"),
+        title: Some("long ".repeat(20)),
+        body: None,
+        published_at: None,
+        url: Some(String::from("https://example.mataroa.blog/blog/post/")),
+    };
+    let date = |d| Date::from_ymd_opt(2015, 10, d).unwrap();
+    let now = date(21).and_hms_opt(16, 29, 0).unwrap();
+
+    let ics = ics(&[(date(22), &post)], date(21), now);
+    assert!(ics.lines().all(|line| line.len() <= MAX_LINE + 1));
+    let unfolded = ics.replace("\r\n ", "");
+    assert!(unfolded.contains(&format!("SUMMARY:Scheduled: {}\r\n", "long ".repeat(20))));
+}
@@ -0,0 +1,94 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use kommandozeile::{color_eyre::eyre::bail, Result};
+
+use crate::{
+    api, args,
+    config::Config,
+    dateformat::{self, PublishDate},
+    links::{self, Blog, Link},
+    Post,
+};
+
+/// Why a link is reported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Problem {
+    /// There is no post with the linked slug.
+    Broken,
+    /// The linked post is a draft.
+    Draft,
+    /// The linked post is published after the post that links to it.
+    Future,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Broken => "broken link",
+            Self::Draft => "link to a draft",
+            Self::Future => "link to a post published later",
+        })
+    }
+}
+
+/// Checks all links between the posts of the blog.
+///
+/// Every link that points to the blog itself is checked that the linked
+/// post exists, and that it is published no later than the post with the
+/// link (or today, if the post with the link is a draft).
+/// All problems are printed, and the command fails if there were any.
+pub fn run(client: &api::Client, config: &Config, cmd: &args::CheckLinks) -> Result<()> {
+    let posts = client.all_posts()?;
+    let blog = Blog::new(&posts, cmd.domain.iter().map(String::as_str));
+    let by_slug = posts
+        .iter()
+        .map(|p| (p.slug.as_str(), p))
+        .collect::<BTreeMap<_, _>>();
+
+    let today = dateformat::today(config.timezone);
+    let mut problems = 0_usize;
+    let mut checked = 0_usize;
+
+    for post in &posts {
+        let Some(body) = post.body.as_deref() else {
+            continue;
+        };
+
+        let linked_at = match post.publish_date() {
+            PublishDate::Date(date) => date,
+            PublishDate::Draft => today,
+        };
+
+        for link in links::find(body, &blog) {
+            checked += 1;
+            let problem = match by_slug.get(link.slug.as_str()).map(|p| p.publish_date()) {
+                None => Problem::Broken,
+                Some(PublishDate::Draft) => Problem::Draft,
+                Some(PublishDate::Date(date)) if date > linked_at => Problem::Future,
+                Some(PublishDate::Date(_)) => continue,
+            };
+
+            problems += 1;
+            report(post, &link, problem, &by_slug);
+        }
+    }
+
+    println!("Checked {checked} links in {} posts", posts.len());
+    if problems > 0 {
+        bail!("Found {problems} problems with links");
+    }
+
+    Ok(())
+}
+
+fn report(post: &Post, link: &Link, problem: Problem, posts: &BTreeMap<&str, &Post>) {
+    print!("{}:{}: {problem} '{}'", post.slug, link.line, link.dest);
+    if let Some(published_at) = posts
+        .get(link.slug.as_str())
+        .and_then(|p| p.published_at.as_deref())
+        .filter(|_| problem == Problem::Future)
+    {
+        print!(" (published at {published_at})");
+    }
+    println!();
+}
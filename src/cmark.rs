@@ -0,0 +1,1120 @@
+use std::{fmt::Write as _, ops::Range, str::FromStr};
+
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, LinkType, Parser, Tag};
+use serde::Deserialize;
+
+use crate::{preview, transform};
+
+// Markdown is formatted by parsing it and writing the events back out in
+// a canonical form:
+//
+// - ATX headings, fenced code blocks, `-` and `1.` list markers, `*emphasis*`,
+//   `**strong**`, and `---` rules
+// - exactly one blank line between blocks, none between the items of a tight list
+// - no trailing whitespace, hard breaks as a backslash
+// - reference links as `[text][label]` or `[label][]`, with all definitions
+//   gathered at the end, in the order they are first used
+// - characters that would otherwise be markup are escaped
+
+/// How the lines of paragraphs are broken.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "WrapSetting")]
+pub enum Wrap {
+    /// Keep the line breaks as they are.
+    #[default]
+    Keep,
+    /// Put every sentence on its own line.
+    Sentence,
+    /// Fill lines up to this many characters.
+    Width(usize),
+}
+
+impl FromStr for Wrap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "sentence" => Ok(Self::Sentence),
+            width => match width.parse() {
+                Ok(0) | Err(_) => Err(format!(
+                    "'{width}' is not 'keep', 'sentence', or a line width"
+                )),
+                Ok(width) => Ok(Self::Width(width)),
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WrapSetting {
+    Width(usize),
+    Name(String),
+}
+
+impl TryFrom<WrapSetting> for Wrap {
+    type Error = String;
+
+    fn try_from(value: WrapSetting) -> Result<Self, Self::Error> {
+        match value {
+            WrapSetting::Width(width) => width.to_string().parse(),
+            WrapSetting::Name(name) => name.parse(),
+        }
+    }
+}
+
+/// Marks a space that must not be broken, e.g. in a code span.
+const KEEP: char = '\u{1}';
+/// Delimit the placeholders of template expressions and wiki-links.
+const PLACEHOLDER_START: char = '\u{e000}';
+const PLACEHOLDER_END: char = '\u{e001}';
+const SOFT_BREAK: char = '\u{2}';
+const HARD_BREAK: char = '\u{3}';
+
+/// Writes `markdown` in the canonical form.
+/// The result is empty or ends with a newline.
+pub fn format(markdown: &str, wrap: Wrap) -> String {
+    let (markdown, kept) = protect(markdown);
+    restore(format_protected(&markdown, wrap), &kept)
+}
+
+fn format_protected(markdown: &str, wrap: Wrap) -> String {
+    let parser = Parser::new_ext(markdown, preview::options());
+
+    let mut definitions = parser
+        .reference_definitions()
+        .iter()
+        .map(|(_, def)| Definition {
+            label: label(&markdown[def.span.clone()]),
+            dest: def.dest.to_string(),
+            title: def.title.as_ref().map(ToString::to_string),
+            span: def.span.clone(),
+        })
+        .collect::<Vec<_>>();
+    definitions.sort_by_key(|d| d.span.start);
+
+    let events = parser.into_offset_iter().collect::<Vec<_>>();
+    let mut writer = Writer {
+        wrap,
+        out: String::with_capacity(markdown.len()),
+        containers: Vec::new(),
+        lists: Vec::new(),
+        loose: loose_lists(&events).into_iter(),
+        inline: None,
+        code: None,
+        table: None,
+        links: Vec::new(),
+        definitions,
+        used: Vec::new(),
+        blank: false,
+        html: None,
+        previous_list: None,
+    };
+    for (event, range) in events {
+        writer.event(event, range);
+    }
+    writer.finish()
+}
+
+/// The label of a reference definition, as it is written in the source.
+fn label(source: &str) -> String {
+    let source = source.trim_start();
+    let source = source.strip_prefix('[').unwrap_or(source);
+    source
+        .find("]:")
+        .map_or(source, |end| &source[..end])
+        .to_owned()
+}
+
+/// Whether each list, in order, is loose, i.e. has blank lines between its items.
+fn loose_lists(events: &[(Event<'_>, Range<usize>)]) -> Vec<bool> {
+    let mut loose = Vec::new();
+    let mut lists = Vec::new();
+    let mut in_item = Vec::new();
+
+    for (event, _) in events {
+        match event {
+            Event::Start(tag) => {
+                if matches!(tag, Tag::Paragraph) && in_item.last() == Some(&true) {
+                    if let Some(&list) = lists.last() {
+                        loose[list] = true;
+                    }
+                }
+                if matches!(tag, Tag::List(_)) {
+                    lists.push(loose.len());
+                    loose.push(false);
+                }
+                in_item.push(matches!(tag, Tag::Item));
+            }
+            Event::End(tag) => {
+                let _ = in_item.pop();
+                if matches!(tag, Tag::List(_)) {
+                    let _ = lists.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    loose
+}
+
+struct Definition {
+    label: String,
+    dest: String,
+    title: Option<String>,
+    span: Range<usize>,
+}
+
+enum Container {
+    Quote,
+    /// A list item or a footnote definition. The marker is written on the
+    /// first line, all other lines are indented.
+    Item {
+        indent: usize,
+        marker: Option<String>,
+    },
+}
+
+struct List {
+    number: Option<u64>,
+    delimiter: char,
+    loose: bool,
+    first: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum InlineKind {
+    Paragraph,
+    /// The text of an item in a tight list, which is not in a paragraph.
+    Implicit,
+    Heading(usize),
+    Cell,
+}
+
+struct Inline {
+    kind: InlineKind,
+    text: String,
+    /// Text that is not escaped yet, since escaping depends on the
+    /// characters around it.
+    raw: String,
+}
+
+struct Table {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>,
+}
+
+struct Writer {
+    wrap: Wrap,
+    out: String,
+    containers: Vec<Container>,
+    lists: Vec<List>,
+    loose: std::vec::IntoIter<bool>,
+    inline: Option<Inline>,
+    /// The info string and content of the current code block.
+    code: Option<(String, String)>,
+    table: Option<Table>,
+    /// Where the text of the currently open links starts.
+    links: Vec<usize>,
+    definitions: Vec<Definition>,
+    /// The definitions that are used, in the order of first use.
+    used: Vec<usize>,
+    /// Whether a blank line is needed before the next block.
+    blank: bool,
+    /// The end of the last line of the current html block.
+    html: Option<usize>,
+    /// The depth and delimiter of a list that directly precedes the next block.
+    previous_list: Option<(usize, char)>,
+}
+
+impl Writer {
+    fn event(&mut self, event: Event<'_>, range: Range<usize>) {
+        if self.html.is_some() && !matches!(event, Event::Html(_)) {
+            self.html = None;
+            self.blank = true;
+        }
+
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                if let Some((_, code)) = &mut self.code {
+                    code.push_str(&text);
+                } else {
+                    self.inline().raw.push_str(&text);
+                }
+            }
+            Event::Code(code) => {
+                let cell = self.inline.as_ref().map(|i| i.kind) == Some(InlineKind::Cell);
+                let mut span = code_span(&code).replace(' ', &KEEP.to_string());
+                if cell {
+                    span = span.replace('|', "\\|");
+                }
+                self.push(&span);
+            }
+            Event::Html(html) => {
+                if self.inline.is_some() {
+                    // line breaks in inline html are wrapped like the text around it
+                    self.push(&html.replace('\n', &SOFT_BREAK.to_string()));
+                } else if !html.ends_with('\n') {
+                    self.push(&html);
+                } else {
+                    if self.html != Some(range.start) {
+                        self.blank |= self.html.is_some();
+                        self.start_block();
+                    }
+                    for line in html.lines() {
+                        self.line(line, true);
+                    }
+                    self.html = Some(range.end);
+                }
+            }
+            Event::FootnoteReference(label) => self.push(&format!("[^{label}]")),
+            Event::SoftBreak => self.push(&SOFT_BREAK.to_string()),
+            Event::HardBreak => self.push(&HARD_BREAK.to_string()),
+            Event::Rule => {
+                self.start_block();
+                self.line("---", true);
+                self.blank = true;
+            }
+            Event::TaskListMarker(checked) => self.push(if checked { "[x] " } else { "[ ] " }),
+        }
+    }
+
+    fn start(&mut self, tag: Tag<'_>) {
+        match tag {
+            Tag::Paragraph => {
+                self.start_block();
+                self.inline = Some(Inline::new(InlineKind::Paragraph));
+            }
+            Tag::Heading(level, ..) => {
+                self.start_block();
+                self.inline = Some(Inline::new(InlineKind::Heading(level as usize)));
+            }
+            Tag::BlockQuote => {
+                self.start_block();
+                self.containers.push(Container::Quote);
+            }
+            Tag::CodeBlock(kind) => {
+                self.start_block();
+                let info = match kind {
+                    CodeBlockKind::Fenced(info) => info.trim().to_owned(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code = Some((info, String::new()));
+            }
+            Tag::List(number) => {
+                let previous = self.previous_list.take();
+                self.start_block();
+                let (delimiter, other) = if number.is_some() {
+                    ('.', ')')
+                } else {
+                    ('-', '*')
+                };
+                // adjacent lists need different markers to stay separate
+                let delimiter = if previous == Some((self.containers.len(), delimiter)) {
+                    other
+                } else {
+                    delimiter
+                };
+                self.lists.push(List {
+                    number,
+                    delimiter,
+                    loose: self.loose.next().unwrap_or_default(),
+                    first: true,
+                });
+            }
+            Tag::Item => {
+                self.previous_list = None;
+                let Some(list) = self.lists.last_mut() else {
+                    return;
+                };
+                let blank = list.loose && !list.first;
+                list.first = false;
+                let marker = match &mut list.number {
+                    Some(number) => {
+                        *number += 1;
+                        format!("{}{} ", *number - 1, list.delimiter)
+                    }
+                    None => format!("{} ", list.delimiter),
+                };
+                if blank {
+                    self.line("", true);
+                }
+                self.blank = false;
+                self.containers.push(Container::Item {
+                    indent: marker.len(),
+                    marker: Some(marker),
+                });
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.start_block();
+                self.containers.push(Container::Item {
+                    indent: 4,
+                    marker: Some(format!("[^{label}]: ")),
+                });
+            }
+            Tag::Table(alignments) => {
+                self.start_block();
+                self.table = Some(Table {
+                    alignments,
+                    rows: Vec::new(),
+                });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableCell => self.inline = Some(Inline::new(InlineKind::Cell)),
+            Tag::Emphasis => self.push("*"),
+            Tag::Strong => self.push("**"),
+            Tag::Strikethrough => self.push("~~"),
+            Tag::Link(..) | Tag::Image(..) => {
+                self.push(if matches!(tag, Tag::Image(..)) {
+                    "!["
+                } else {
+                    "["
+                });
+                let at = self.inline().text.len();
+                self.links.push(at);
+            }
+        }
+    }
+
+    fn end(&mut self, tag: Tag<'_>) {
+        match tag {
+            Tag::Paragraph => self.paragraph(),
+            Tag::Heading(level, ..) => {
+                let text = self
+                    .take_inline()
+                    .replace([SOFT_BREAK, HARD_BREAK, KEEP], " ");
+                let text = text.trim();
+                // a trailing `#` would be taken as the closing sequence
+                let text = match text.strip_suffix('#') {
+                    Some(rest) if rest.is_empty() || rest.ends_with([' ', '#']) => {
+                        format!("{rest}\\#")
+                    }
+                    _ => text.to_owned(),
+                };
+                let hashes = "#".repeat(level as usize);
+                self.line(format!("{hashes} {text}").trim_end(), true);
+                self.blank = true;
+            }
+            Tag::CodeBlock(_) => {
+                let (info, code) = self.code.take().unwrap_or_default();
+                let (char, longest) = if info.contains('`') {
+                    ('~', longest_run(&code, '~'))
+                } else {
+                    ('`', longest_run(&code, '`'))
+                };
+                let fence = char.to_string().repeat(longest.max(2) + 1);
+                self.line(&format!("{fence}{info}"), true);
+                if !code.is_empty() {
+                    for line in code.strip_suffix('\n').unwrap_or(&code).split('\n') {
+                        self.line(line, false);
+                    }
+                }
+                self.line(&fence, true);
+                self.blank = true;
+            }
+            Tag::List(_) => {
+                if let Some(list) = self.lists.pop() {
+                    self.previous_list = Some((self.containers.len(), list.delimiter));
+                }
+                self.blank = true;
+            }
+            Tag::Item => {
+                self.end_container();
+                self.blank = false;
+            }
+            Tag::BlockQuote | Tag::FootnoteDefinition(_) => {
+                self.end_container();
+                self.blank = true;
+            }
+            Tag::Table(_) => {
+                if let Some(table) = self.table.take() {
+                    self.table(&table);
+                }
+                self.blank = true;
+            }
+            Tag::TableHead | Tag::TableRow => {}
+            Tag::TableCell => {
+                let cell = self.take_inline().replace(KEEP, " ");
+                if let Some(row) = self.table.as_mut().and_then(|t| t.rows.last_mut()) {
+                    row.push(cell.trim().to_owned());
+                }
+            }
+            Tag::Emphasis => self.push("*"),
+            Tag::Strong => self.push("**"),
+            Tag::Strikethrough => self.push("~~"),
+            Tag::Link(kind, dest, title) | Tag::Image(kind, dest, title) => {
+                self.close_link(kind, &dest, &title);
+            }
+        }
+    }
+
+    fn close_link(&mut self, kind: LinkType, dest: &str, title: &str) {
+        let at = self.links.pop().unwrap_or_default();
+        let title = Some(title).filter(|t| !t.is_empty());
+        let definition = self
+            .definitions
+            .iter()
+            .position(|d| d.dest == dest && d.title.as_deref() == title);
+
+        let inline = self.inline();
+        inline.flush();
+        let closing = match (kind, definition) {
+            (LinkType::Autolink | LinkType::Email, _) => {
+                // the text of an autolink is its destination
+                inline.text.truncate(at.saturating_sub(1));
+                format!("<{dest}>")
+            }
+            (LinkType::Reference | LinkType::Collapsed | LinkType::Shortcut, Some(definition)) => {
+                let label = self.definitions[definition].label.clone();
+                let text = self.inline().text[at..].to_owned();
+                if !self.used.contains(&definition) {
+                    self.used.push(definition);
+                }
+                if text.to_lowercase() == label.to_lowercase() {
+                    String::from("][]")
+                } else {
+                    format!("][{label}]")
+                }
+            }
+            _ => format!("]({})", destination(dest, title)).replace(' ', &KEEP.to_string()),
+        };
+        self.inline().text.push_str(&closing);
+    }
+
+    /// The inline content that is currently written, which starts the text
+    /// of a tight list item if there is none.
+    fn inline(&mut self) -> &mut Inline {
+        if self.inline.is_none() {
+            self.start_block();
+        }
+        self.inline
+            .get_or_insert_with(|| Inline::new(InlineKind::Implicit))
+    }
+
+    fn push(&mut self, markup: &str) {
+        let inline = self.inline();
+        inline.flush();
+        inline.text.push_str(markup);
+    }
+
+    fn take_inline(&mut self) -> String {
+        self.inline.take().map(Inline::finish).unwrap_or_default()
+    }
+
+    /// Writes a blank line if one is needed before a new block.
+    fn start_block(&mut self) {
+        if self
+            .inline
+            .as_ref()
+            .is_some_and(|i| i.kind == InlineKind::Implicit)
+        {
+            self.paragraph();
+            self.blank = false;
+        }
+
+        let at_marker = self.containers.iter().any(|c| {
+            matches!(
+                c,
+                Container::Item {
+                    marker: Some(_),
+                    ..
+                }
+            )
+        });
+        if self.blank && !at_marker && !self.out.is_empty() {
+            self.line("", true);
+        }
+        self.blank = false;
+        self.previous_list = None;
+    }
+
+    fn end_container(&mut self) {
+        if self
+            .inline
+            .as_ref()
+            .is_some_and(|i| i.kind == InlineKind::Implicit)
+        {
+            self.paragraph();
+        }
+        // an empty item still needs its marker
+        if let Some(Container::Item {
+            marker: Some(_), ..
+        }) = self.containers.last()
+        {
+            self.line("", true);
+        }
+        let _ = self.containers.pop();
+    }
+
+    fn paragraph(&mut self) {
+        let text = self.take_inline();
+        for line in wrap(&text, self.wrap) {
+            self.line(&line, true);
+        }
+        self.blank = true;
+    }
+
+    fn table(&mut self, table: &Table) {
+        let columns = table.alignments.len();
+        let width = |column: usize| {
+            table
+                .rows
+                .iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or_default()
+                .max(3)
+        };
+        let widths = (0..columns).map(width).collect::<Vec<_>>();
+
+        let row = |cells: &[String]| {
+            let cells = widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let cell = cells.get(column).map_or("", String::as_str);
+                    let pad = width - cell.chars().count();
+                    match table.alignments[column] {
+                        Alignment::Right => format!("{}{cell}", " ".repeat(pad)),
+                        _ => format!("{cell}{}", " ".repeat(pad)),
+                    }
+                })
+                .collect::<Vec<_>>();
+            format!("| {} |", cells.join(" | "))
+        };
+
+        let delimiters = widths
+            .iter()
+            .zip(&table.alignments)
+            .map(|(width, alignment)| match alignment {
+                Alignment::None => "-".repeat(*width),
+                Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+                Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+                Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+            })
+            .collect::<Vec<_>>();
+
+        let mut rows = table.rows.iter();
+        if let Some(head) = rows.next() {
+            self.line(&row(head), true);
+            self.line(&format!("| {} |", delimiters.join(" | ")), true);
+        }
+        for cells in rows {
+            self.line(&row(cells), true);
+        }
+    }
+
+    /// Writes a line, prefixed by the markers and indentation of the
+    /// containers it is in.
+    fn line(&mut self, text: &str, trim: bool) {
+        let mut prefix = String::new();
+        for container in &mut self.containers {
+            match container {
+                Container::Quote => prefix.push_str("> "),
+                Container::Item { indent, marker } => match marker.take() {
+                    Some(marker) => prefix.push_str(&marker),
+                    None => prefix.push_str(&" ".repeat(*indent)),
+                },
+            }
+        }
+
+        if text.is_empty() {
+            self.out.push_str(prefix.trim_end());
+        } else {
+            self.out.push_str(&prefix);
+            self.out.push_str(if trim { text.trim_end() } else { text });
+        }
+        self.out.push('\n');
+    }
+
+    fn finish(self) -> String {
+        let mut out = self.out.trim_end().to_owned();
+
+        let unused = (0..self.definitions.len()).filter(|d| !self.used.contains(d));
+        let order = self.used.iter().copied().chain(unused).collect::<Vec<_>>();
+        for (idx, definition) in order.into_iter().enumerate() {
+            let Definition {
+                label, dest, title, ..
+            } = &self.definitions[definition];
+            out.push_str(if idx == 0 && !out.is_empty() {
+                "\n\n"
+            } else {
+                "\n"
+            });
+            let _ = write!(out, "[{label}]: {}", destination(dest, title.as_deref()));
+        }
+
+        let out = out.trim_start_matches('\n');
+        if out.is_empty() {
+            String::new()
+        } else {
+            format!("{out}\n")
+        }
+    }
+}
+
+impl Inline {
+    const fn new(kind: InlineKind) -> Self {
+        Self {
+            kind,
+            text: String::new(),
+            raw: String::new(),
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.raw.is_empty() {
+            let escaped = escape(&self.raw, self.kind == InlineKind::Cell);
+            self.text.push_str(&escaped);
+            self.raw.clear();
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        self.text
+    }
+}
+
+/// Escapes the characters of `text` that would otherwise be markup.
+fn escape(text: &str, in_cell: bool) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut out = String::with_capacity(text.len());
+
+    for (idx, &c) in chars.iter().enumerate() {
+        let prev = idx.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(idx + 1).copied();
+        let escape = match c {
+            '\\' | '`' | '*' | '[' | ']' | '~' => true,
+            // `_` can't start emphasis inside a word
+            '_' => {
+                !(prev.is_some_and(char::is_alphanumeric)
+                    && next.is_some_and(char::is_alphanumeric))
+            }
+            '<' => next.is_some_and(|n| n.is_ascii_alphabetic() || matches!(n, '/' | '!' | '?')),
+            '&' => {
+                let name = chars[idx + 1..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '#')
+                    .count();
+                name > 0 && chars.get(idx + 1 + name) == Some(&';')
+            }
+            '|' => in_cell,
+            _ => false,
+        };
+        if escape {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+/// Replaces the template expressions (`{{ ... }}`) and wiki-links (`[[...]]`)
+/// in `markdown` with placeholders, which contain no markup and no spaces,
+/// so that they are kept byte-for-byte by [`restore`].
+fn protect(markdown: &str) -> (String, Vec<&str>) {
+    let mut spans = kept_spans(markdown);
+    spans.sort_by_key(|span| span.start);
+
+    let mut out = String::with_capacity(markdown.len());
+    let mut kept = Vec::new();
+    let mut at = 0;
+    for span in spans {
+        if span.start < at {
+            continue;
+        }
+        out.push_str(&markdown[at..span.start]);
+        let _ = write!(out, "{PLACEHOLDER_START}{}{PLACEHOLDER_END}", kept.len());
+        kept.push(&markdown[span.clone()]);
+        at = span.end;
+    }
+    out.push_str(&markdown[at..]);
+    (out, kept)
+}
+
+/// Puts the spans that [`protect`] replaced back.
+fn restore(mut formatted: String, kept: &[&str]) -> String {
+    for (idx, span) in kept.iter().enumerate().rev() {
+        formatted = formatted.replace(&format!("{PLACEHOLDER_START}{idx}{PLACEHOLDER_END}"), span);
+    }
+    formatted
+}
+
+/// The template expressions and wiki-links in `text`.
+fn kept_spans(text: &str) -> Vec<Range<usize>> {
+    let mut spans = transform::template_spans(text);
+    let mut at = 0;
+    while let Some(start) = text[at..].find("[[").map(|s| s + at) {
+        at = start + 2;
+        let Some(len) = text[at..].find("]]") else {
+            break;
+        };
+        if !text[at..at + len].contains(['\n', '[']) {
+            at += len + 2;
+            spans.push(start..at);
+        }
+    }
+    spans
+}
+
+/// Escapes the start of a paragraph line that would otherwise start a block.
+fn guard(line: &str) -> String {
+    let Some(first) = line.chars().next() else {
+        return String::new();
+    };
+    let rest = &line[first.len_utf8()..];
+
+    let escape_first = match first {
+        '#' => {
+            let hashes = rest.len() - rest.trim_start_matches('#').len();
+            hashes < 6 && (rest[hashes..].is_empty() || rest[hashes..].starts_with(' '))
+        }
+        '>' => true,
+        '-' | '+' | '=' => {
+            rest.is_empty() || rest.starts_with(' ') || line.chars().all(|c| c == first || c == ' ')
+        }
+        _ => false,
+    };
+    if escape_first {
+        return format!("\\{line}");
+    }
+
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if (1..10).contains(&digits) {
+        let after = &line[digits..];
+        if after.starts_with(['.', ')']) && (after.len() == 1 || after[1..].starts_with(' ')) {
+            return format!("{}\\{after}", &line[..digits]);
+        }
+    }
+
+    line.to_owned()
+}
+
+/// Breaks the text of a paragraph into lines.
+fn wrap(text: &str, wrap: Wrap) -> Vec<String> {
+    let segments = text.split(HARD_BREAK).collect::<Vec<_>>();
+    let mut lines = Vec::new();
+
+    for (idx, segment) in segments.iter().enumerate() {
+        let words = segment
+            .split([SOFT_BREAK, ' '])
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>();
+        let mut segment = match wrap {
+            Wrap::Keep => segment
+                .split(SOFT_BREAK)
+                .map(|line| line.trim().to_owned())
+                .collect(),
+            Wrap::Sentence => sentences(&words),
+            Wrap::Width(width) => fill(&words, width),
+        };
+        if idx + 1 < segments.len() {
+            if let Some(last) = segment.last_mut() {
+                last.push('\\');
+            }
+        }
+        lines.extend(segment);
+    }
+
+    lines
+        .into_iter()
+        .map(|line| guard(&line.replace(KEEP, " ")))
+        .collect()
+}
+
+fn fill(words: &[&str], width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut len = 0;
+
+    for word in words {
+        let word_len = word.chars().count();
+        if !line.is_empty() && len + 1 + word_len > width && !starts_html_block(word) {
+            lines.push(std::mem::take(&mut line));
+            len = 0;
+        }
+        if !line.is_empty() {
+            line.push(' ');
+            len += 1;
+        }
+        line.push_str(word);
+        len += word_len;
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn sentences(words: &[&str]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+
+    for (idx, word) in words.iter().enumerate() {
+        line.push(*word);
+        let end = word.trim_end_matches(['"', '\'', ')', '*', '_', '”', '’']);
+        let next = words
+            .get(idx + 1)
+            .and_then(|w| w.chars().find(|c| c.is_alphanumeric()));
+        let html = words.get(idx + 1).is_some_and(|w| starts_html_block(w));
+        if end.ends_with(['.', '!', '?']) && next.is_some_and(char::is_uppercase) && !html {
+            lines.push(line.join(" "));
+            line.clear();
+        }
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line.join(" "));
+    }
+    lines
+}
+
+/// Whether a line that starts with `word` would start an HTML block, which
+/// can interrupt a paragraph, so that the paragraph must not be wrapped there.
+fn starts_html_block(word: &str) -> bool {
+    /// The tags that start an HTML block of type 1 or 6 in CommonMark.
+    const TAGS: &[&str] = &[
+        "address",
+        "article",
+        "aside",
+        "base",
+        "basefont",
+        "blockquote",
+        "body",
+        "caption",
+        "center",
+        "col",
+        "colgroup",
+        "dd",
+        "details",
+        "dialog",
+        "dir",
+        "div",
+        "dl",
+        "dt",
+        "fieldset",
+        "figcaption",
+        "figure",
+        "footer",
+        "form",
+        "frame",
+        "frameset",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "head",
+        "header",
+        "hr",
+        "html",
+        "iframe",
+        "legend",
+        "li",
+        "link",
+        "main",
+        "menu",
+        "menuitem",
+        "nav",
+        "noframes",
+        "ol",
+        "optgroup",
+        "option",
+        "p",
+        "param",
+        "pre",
+        "script",
+        "search",
+        "section",
+        "style",
+        "summary",
+        "table",
+        "tbody",
+        "td",
+        "textarea",
+        "tfoot",
+        "th",
+        "thead",
+        "title",
+        "tr",
+        "track",
+        "ul",
+    ];
+
+    let Some(rest) = word.strip_prefix('<') else {
+        return false;
+    };
+    if rest.starts_with(['!', '?']) {
+        return true;
+    }
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    let name = rest
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .map_or(rest, |end| &rest[..end]);
+    let after = &rest[name.len()..];
+    TAGS.iter().any(|tag| tag.eq_ignore_ascii_case(name))
+        && (after.is_empty()
+            || after.starts_with(|c: char| c.is_whitespace() || c == '>')
+            || after.starts_with("/>"))
+}
+
+fn code_span(code: &str) -> String {
+    let mut ticks = 1;
+    while has_run(code, '`', ticks) {
+        ticks += 1;
+    }
+    let fence = "`".repeat(ticks);
+    let pad = code.starts_with('`')
+        || code.ends_with('`')
+        || (code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty());
+    let pad = if pad { " " } else { "" };
+    format!("{fence}{pad}{code}{pad}{fence}")
+}
+
+/// Whether `text` contains a run of exactly `len` times `c`.
+fn has_run(text: &str, c: char, len: usize) -> bool {
+    text.split(|ch| ch != c).any(|run| run.len() == len)
+}
+
+fn longest_run(text: &str, c: char) -> usize {
+    text.split(|ch| ch != c)
+        .map(str::len)
+        .max()
+        .unwrap_or_default()
+}
+
+fn destination(dest: &str, title: Option<&str>) -> String {
+    let needs_brackets = dest.is_empty()
+        || dest.contains(|c: char| c.is_whitespace() || c.is_control() || "<>()".contains(c));
+    let mut out = if needs_brackets {
+        format!("<{}>", dest.replace('<', "\\<").replace('>', "\\>"))
+    } else {
+        dest.to_owned()
+    };
+    if let Some(title) = title {
+        let title = title.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = write!(out, " \"{title}\"");
+    }
+    out
+}
+
+#[test]
+fn format_markdown() {
+    let markdown = concat!(
+        "Heading\n",
+        "=======\n",
+        "\n",
+        "Some _emphasis_ and __strong__ text   \n",
+        "with a [reference][Ref], a [shortcut], and `code`.\n",
+        "\n",
+        "\n",
+        "[ref]: https://example.com \"Title\"\n",
+        "[shortcut]: </a b>\n",
+        "\n",
+        "    indented code  \n",
+        "\n",
+        "* one\n",
+        "* two\n",
+        "    + nested\n",
+        "\n",
+        "1) first\n",
+        "1) second\n",
+        "\n",
+        "> quoted\\\n",
+        "> 2. not a list, \\*not emphasis\\*, snake_case\n",
+    );
+
+    let expected = concat!(
+        "# Heading\n",
+        "\n",
+        "Some *emphasis* and **strong** text\\\n",
+        "with a [reference][ref], a [shortcut][], and `code`.\n",
+        "\n",
+        "```\n",
+        "indented code  \n",
+        "```\n",
+        "\n",
+        "- one\n",
+        "- two\n",
+        "  - nested\n",
+        "\n",
+        "1. first\n",
+        "2. second\n",
+        "\n",
+        "> quoted\\\n",
+        "> 2\\. not a list, \\*not emphasis\\*, snake_case\n",
+        "\n",
+        "[ref]: https://example.com \"Title\"\n",
+        "[shortcut]: </a b>\n",
+    );
+
+    let formatted = format(markdown, Wrap::Keep);
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted, Wrap::Keep), expected);
+
+    let paragraph = "One sentence. Another one, which is\nlonger! The `last one` ends - here.";
+    assert_eq!(
+        format(paragraph, Wrap::Sentence),
+        "One sentence.\nAnother one, which is longer!\nThe `last one` ends - here.\n"
+    );
+    let wrapped = format(paragraph, Wrap::Width(15));
+    assert_eq!(
+        wrapped,
+        "One sentence.\nAnother one,\nwhich is\nlonger! The\n`last one` ends\n\\- here.\n"
+    );
+    assert_eq!(format(&wrapped, Wrap::Width(15)), wrapped);
+}
+
+#[test]
+fn format_keeps_templates_and_wiki_links() {
+    let markdown = concat!(
+        "See [[Other Note]] and [[other-note|the note]], ![[image.png]].\n",
+        "\n",
+        "{{#include ../_partials/footer.md}}\n",
+        "\n",
+        "{{ youtube(\"a_b\", title=\"A [video] *here*\") }} and {{ title | upper }}\n",
+    );
+    assert_eq!(format(markdown, Wrap::Keep), markdown);
+    let wrapped = format(markdown, Wrap::Width(10));
+    for span in [
+        "[[Other Note]]",
+        "{{#include ../_partials/footer.md}}",
+        "{{ title | upper }}",
+    ] {
+        assert!(wrapped.contains(span), "{span} in {wrapped}");
+    }
+}
+
+#[test]
+fn format_keeps_inline_html_in_paragraphs() {
+    let markdown = concat!(
+        "Some text with an inline <div>block tag</div> and <span>span</span>.\n",
+        "\n",
+        "A sentence. <DIV class=\"x\">Another</DIV> one. <!-- a comment -->\n",
+    );
+    for wrap in [Wrap::Width(10), Wrap::Width(30), Wrap::Sentence] {
+        let formatted = format(markdown, wrap);
+        assert!(
+            !formatted.lines().any(starts_html_block),
+            "{wrap:?}: {formatted}"
+        );
+        assert_eq!(format(&formatted, wrap), formatted);
+        assert_eq!(
+            format(&formatted, Wrap::Width(usize::MAX)),
+            format(markdown, Wrap::Width(usize::MAX))
+        );
+    }
+}
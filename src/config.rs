@@ -0,0 +1,277 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::Weekday;
+use chrono_tz::Tz;
+use kommandozeile::{
+    color_eyre::eyre::{bail, OptionExt as _, WrapErr as _},
+    tracing::debug,
+    Result,
+};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{cmark::Wrap, expire::Expired, index::GroupBy, transform::Transforms};
+
+/// The configuration file, by default at `<config dir>/mataroa/config.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The time zone of the blog, e.g. `"Europe/Berlin"`.
+    #[serde(default, deserialize_with = "timezone")]
+    pub timezone: Option<Tz>,
+
+    /// Snippets that can be used in post bodies, by name.
+    #[serde(default)]
+    pub shortcodes: BTreeMap<String, Shortcode>,
+
+    /// The transforms that are applied to post bodies before publishing.
+    #[serde(default)]
+    pub transforms: Transforms,
+
+    /// How `roa fmt` formats post files.
+    #[serde(default)]
+    pub fmt: Fmt,
+
+    /// How `roa note` names notes.
+    #[serde(default)]
+    pub note: Note,
+
+    /// The publishing cadence for `roa schedule`.
+    #[serde(default)]
+    pub schedule: Schedule,
+
+    /// Where `roa scheduler` keeps its jobs.
+    #[serde(default)]
+    pub scheduler: Scheduler,
+
+    /// How `roa index` renders the index post.
+    #[serde(default)]
+    pub index: Index,
+
+    /// The retention policies for `roa expire`.
+    #[serde(default)]
+    pub expire: Expire,
+
+    /// Named sets of settings that are used instead of the top-level ones
+    /// when selected with `--profile`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// The directory of the config file, which also contains the templates
+    /// for new posts.
+    #[serde(skip)]
+    pub dir: Option<PathBuf>,
+}
+
+/// Settings that can differ per profile.
+/// Settings that a profile doesn't make are taken from the top level.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default)]
+    pub transforms: Transforms,
+    #[serde(default)]
+    pub expire: Expire,
+}
+
+/// Settings for `roa fmt`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fmt {
+    /// How paragraphs are wrapped: `"keep"`, `"sentence"`, or a line width.
+    #[serde(default)]
+    pub wrap: Wrap,
+}
+
+/// Settings for `roa note`.
+///
+/// All settings are template expressions, which can use the variables `text`,
+/// `first_line`, `first_sentence`, `date`, and `time`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Note {
+    /// The title of a note.
+    pub title: String,
+    /// The slug of a note. mataroa derives the slug from the title when a
+    /// post is created, so the note is renamed to it right afterwards.
+    pub slug: String,
+    /// The title of the daily post that notes are appended to with
+    /// `--append-to-daily`. Its slug is derived from the title.
+    pub daily_title: String,
+}
+
+impl Default for Note {
+    fn default() -> Self {
+        Self {
+            title: String::from("{{ first_sentence }}"),
+            slug: String::from("{{ date }}-{{ time }}"),
+            daily_title: String::from("Notes from {{ date }}"),
+        }
+    }
+}
+
+/// Settings for `roa schedule`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    /// The weekdays on which posts are published, e.g. `["tue", "fri"]`.
+    #[serde(default, deserialize_with = "weekdays")]
+    pub cadence: Vec<Weekday>,
+}
+
+/// Settings for `roa scheduler`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scheduler {
+    /// The file that contains the queued jobs,
+    /// by default `<data dir>/mataroa/jobs.json`.
+    #[serde(default)]
+    pub queue: Option<PathBuf>,
+}
+
+/// Settings for `roa index`.
+///
+/// The `heading` can use the variables `year`, `month`, and `date` (the first
+/// day of the group), and the `item` can use `title`, `slug`, `url`, and
+/// `published_at`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Index {
+    /// The slug of the index post.
+    pub slug: Option<String>,
+    /// Whether the posts are grouped by `"year"` or by `"month"`.
+    pub group_by: GroupBy,
+    /// The heading of a group, by default the year, or the month and year.
+    pub heading: Option<String>,
+    /// A post in a group.
+    pub item: String,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            slug: None,
+            group_by: GroupBy::Month,
+            heading: None,
+            item: String::from("- {{ published_at }}: [{{ title }}]({{ url }})"),
+        }
+    }
+}
+
+/// Settings for `roa expire`.
+/// Unset policies are off.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Expire {
+    /// What happens to a post on its `expires` date:
+    /// `"draft"` reverts it to a draft, and `"delete"` deletes it.
+    pub expired: Option<Expired>,
+    /// Delete drafts whose title and body haven't changed for this many days.
+    pub stale_drafts: Option<u32>,
+    /// The directory for the backups of deleted posts,
+    /// by default `<data dir>/mataroa/backups`.
+    pub backups: Option<PathBuf>,
+    /// The file that records since when the drafts are unchanged,
+    /// by default `<data dir>/mataroa/drafts.json`.
+    pub state: Option<PathBuf>,
+}
+
+impl Expire {
+    /// The settings of `self`, and those of `other` where `self` has none.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            expired: self.expired.or(other.expired),
+            stale_drafts: self.stale_drafts.or(other.stale_drafts),
+            backups: self.backups.or(other.backups),
+            state: self.state.or(other.state),
+        }
+    }
+}
+
+fn timezone<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Tz>, D::Error> {
+    let tz = String::deserialize(deserializer)?;
+    tz.parse::<Tz>().map(Some).map_err(de::Error::custom)
+}
+
+fn weekdays<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Weekday>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|day| {
+            day.parse::<Weekday>()
+                .map_err(|_| de::Error::custom(format!("invalid weekday '{day}'")))
+        })
+        .collect()
+}
+
+/// A markdown snippet that is inserted with `{{ name(param="value") }}`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Shortcode {
+    /// The names of the parameters, in the order they can be passed positionally.
+    #[serde(default)]
+    pub params: Vec<String>,
+    /// The markdown to insert, which can use the parameters as variables.
+    pub body: String,
+}
+
+impl Config {
+    /// Loads the config from `path`, or from the default location if no path
+    /// is given. Only an explicitly given file is required to exist.
+    ///
+    /// If a `profile` is given, its settings replace the top-level ones.
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<Self> {
+        let mut config = Self::read(path)?;
+
+        if let Some(name) = profile {
+            let Some(profile) = config.profiles.get(name) else {
+                bail!("There is no profile '{name}' in the config");
+            };
+            config.transforms = profile.transforms.or(config.transforms);
+            config.expire = profile.expire.clone().or(config.expire);
+        }
+
+        Ok(config)
+    }
+
+    /// The directory that contains the templates for `roa new --template`.
+    pub fn templates_dir(&self) -> Result<PathBuf> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or_eyre("There is no config directory for the templates")?;
+        Ok(dir.join("templates"))
+    }
+
+    fn read(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        let dir = path.parent().map(Path::to_path_buf);
+
+        if !required && !path.exists() {
+            debug!(path = %path.display(), "No config file");
+            return Ok(Self {
+                dir,
+                ..Self::default()
+            });
+        }
+
+        let config = fs::read_to_string(&path)
+            .wrap_err_with(|| format!("Failed to read the config file '{}'", path.display()))?;
+        let config = toml::from_str::<Self>(&config)
+            .wrap_err_with(|| format!("Invalid config file '{}'", path.display()))?;
+        debug!(path = %path.display(), ?config, "Loaded config");
+        Ok(Self { dir, ..config })
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("mataroa").join("config.toml"))
+}
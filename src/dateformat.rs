@@ -1,9 +1,14 @@
-use std::fmt;
+use std::str::FromStr;
 
-use chrono::NaiveDate as Date;
+use chrono::{Datelike as _, Days, Months, NaiveDate as Date, NaiveDateTime, Utc, Weekday};
+use chrono_tz::Tz;
 use human_date_parser::ParseResult;
-use kommandozeile::{color_eyre::eyre::eyre, Result};
-use serde::{de, Deserializer, Serializer};
+use kommandozeile::{
+    color_eyre::eyre::{bail, eyre, WrapErr as _},
+    tracing::warn,
+    Result,
+};
+use serde::Serializer;
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub enum PublishDate {
@@ -12,70 +17,370 @@ pub enum PublishDate {
     Date(Date),
 }
 
-pub fn parse(value: &str) -> Result<PublishDate> {
+/// The current date in the time zone of the blog, or the local one if `tz`
+/// is `None`.
+pub fn today(tz: Option<Tz>) -> Date {
+    now(tz).date()
+}
+
+/// The current date and time in the time zone of the blog, or the local one
+/// if `tz` is `None`.
+pub fn now(tz: Option<Tz>) -> NaiveDateTime {
+    tz.map_or_else(
+        || chrono::Local::now().naive_local(),
+        |tz| Utc::now().with_timezone(&tz).naive_local(),
+    )
+}
+
+/// Parses a publish date, where relative dates like `tomorrow` are relative
+/// to the current date in the time zone `tz` of the blog.
+/// Warns if that is a different day than the same date locally.
+///
+/// Dates that are relative to another post can't be looked up and fail.
+pub fn parse(value: &str, tz: Option<Tz>) -> Result<PublishDate> {
+    parse_with(value, tz, |slug| {
+        bail!("The date of the post '{slug}' can only be looked up on the command line")
+    })
+}
+
+/// Like [`parse`], but dates that are relative to another post, like
+/// `2 weeks after <slug>`, use the date of that post from `post_date`.
+pub fn parse_with(
+    value: &str,
+    tz: Option<Tz>,
+    post_date: impl FnOnce(&str) -> Result<PublishDate>,
+) -> Result<PublishDate> {
+    if let Some(Expr::Post(slug, offset)) = Expr::parse(value) {
+        let PublishDate::Date(date) = post_date(slug)? else {
+            bail!("The post '{slug}' is a draft and has no date");
+        };
+        return offset
+            .add_to(date)
+            .map(PublishDate::Date)
+            .ok_or_else(|| eyre!("'{value}' is out of range"));
+    }
+
+    let date = parse_in(value, tz)?;
+    if let (Some(tz), PublishDate::Date(date)) = (tz, date) {
+        if let Ok(PublishDate::Date(local)) = parse_in(value, None) {
+            if local != date {
+                warn!(
+                    "'{value}' is {date} in the time zone of the blog ({tz}), but {local} locally"
+                );
+            }
+        }
+    }
+    Ok(date)
+}
+
+/// Whether `value` is a date that is relative to another post, like
+/// `2 weeks after <slug>`, which needs to be looked up.
+pub fn is_post_relative(value: &str) -> bool {
+    matches!(Expr::parse(value), Some(Expr::Post(..)))
+}
+
+/// Whether `value` is a date that does not depend on the current date,
+/// i.e. an ISO 8601 date or a draft.
+pub fn is_absolute(value: &str) -> bool {
+    matches!(parse_in(value, None), Ok(PublishDate::Draft))
+        || Date::parse_from_str(value, "%Y-%m-%d").is_ok()
+}
+
+/// Parses the optional date `value` of the frontmatter key `key`, like [`parse`].
+pub fn from_value(
+    key: &str,
+    value: Option<serde_json::Value>,
+    tz: Option<Tz>,
+) -> Result<Option<PublishDate>> {
+    match value {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::String(value)) => parse(&value, tz)
+            .map(Some)
+            .wrap_err_with(|| format!("Invalid `{key}`")),
+        Some(value) => bail!("Invalid `{key}`: expected a date, got {value}"),
+    }
+}
+
+fn parse_in(value: &str, tz: Option<Tz>) -> Result<PublishDate> {
     if value.is_empty() || value.eq_ignore_ascii_case("draft") || value.eq_ignore_ascii_case("none")
     {
         return Ok(PublishDate::Draft);
     }
+    if let Some(expr) = Expr::parse(value) {
+        return expr
+            .eval(today(tz))
+            .map(PublishDate::Date)
+            .ok_or_else(|| eyre!("'{value}' is out of range"));
+    }
     match human_date_parser::from_human_time(value) {
         Ok(ParseResult::Date(date)) => Ok(PublishDate::Date(date)),
-        Ok(ParseResult::DateTime(date)) => Ok(PublishDate::Date(date.date_naive())),
+        Ok(ParseResult::DateTime(date)) => Ok(PublishDate::Date(tz.map_or_else(
+            || date.date_naive(),
+            |tz| date.with_timezone(&tz).date_naive(),
+        ))),
         Ok(ParseResult::Time(_)) => Err(eyre!("need a date, not just a time")),
         Err(_) => Ok(PublishDate::Date(Date::parse_from_str(value, "%Y-%m-%d")?)),
     }
 }
 
-#[allow(clippy::trivially_copy_pass_by_ref)]
-pub fn serialize<S: Serializer>(
-    option: &Option<PublishDate>,
-    serializer: S,
-) -> Result<S::Ok, S::Error> {
-    match option {
-        Some(PublishDate::Date(date)) => serializer.collect_str(&date.format("%Y-%m-%d")),
-        Some(PublishDate::Draft) => serializer.serialize_str(""),
-        None => serializer.serialize_none(),
-    }
+/// The date expressions that are evaluated here instead of by
+/// `human_date_parser`, either because they are not supported by it, or
+/// because it would evaluate them relative to the local date instead of the
+/// date of the blog.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Expr<'a> {
+    /// `today`, `tomorrow`, `yesterday`, `+3d`, `-2w`, `next week`
+    Today(Offset),
+    /// `friday`, `this friday`, `next friday`, `last friday`, like
+    /// `human_date_parser`: the next day with that weekday (or today) plus
+    /// the given number of weeks
+    Weekday(i64, Weekday),
+    /// `start of month`, `end of next week`, `end of last year`
+    Bound(Bound, Unit, i64),
+    /// `same as <slug>`, `2 weeks after <slug>`, `3 days before <slug>`
+    Post(&'a str, Offset),
 }
 
-pub fn deserialize<'a, D: Deserializer<'a>>(
-    deserializer: D,
-) -> Result<Option<PublishDate>, D::Error> {
-    deserializer.deserialize_option(Visitor::<false>)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Bound {
+    Start,
+    End,
 }
 
-struct Visitor<const INNER: bool>;
+/// A number of days, weeks, months, or years, e.g. `+3d` or `2w`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Offset(i64, Unit);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Unit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
 
-impl<'de> de::Visitor<'de> for Visitor<false> {
-    type Value = Option<PublishDate>;
+impl<'a> Expr<'a> {
+    fn parse(value: &'a str) -> Option<Self> {
+        let words = value.split_whitespace().collect::<Vec<_>>();
+        let lower = words.iter().map(|w| w.to_lowercase()).collect::<Vec<_>>();
+        let lower = lower.iter().map(String::as_str).collect::<Vec<_>>();
+        let slug = words.last().copied().unwrap_or_default();
 
-    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        formatter.write_str("an optional ISO 8601 date")
+        let expr = match lower.as_slice() {
+            ["today"] => Self::Today(Offset(0, Unit::Day)),
+            ["tomorrow"] => Self::Today(Offset(1, Unit::Day)),
+            ["yesterday"] => Self::Today(Offset(-1, Unit::Day)),
+            [offset] if offset.starts_with(['+', '-']) => Self::Today(Offset::parse(offset)?),
+            [weekday] => Self::Weekday(0, weekday.parse().ok()?),
+            ["this", word] => Self::relative(0, word)?,
+            ["next", word] => Self::relative(1, word)?,
+            ["last", word] => Self::relative(-1, word)?,
+            [bound, "of", rest @ ..] => {
+                let bound = match *bound {
+                    "start" | "beginning" => Bound::Start,
+                    "end" => Bound::End,
+                    _ => return None,
+                };
+                let (shift, unit) = match rest {
+                    [unit] | ["this", unit] => (0, unit),
+                    ["next", unit] => (1, unit),
+                    ["last", unit] => (-1, unit),
+                    _ => return None,
+                };
+                let unit = Unit::parse(unit).filter(|u| *u != Unit::Day)?;
+                Self::Bound(bound, unit, shift)
+            }
+            ["same", "as", _] | ["same", "day", "as", _] => Self::Post(slug, Offset(0, Unit::Day)),
+            [offset @ .., dir @ ("after" | "before"), _] => {
+                let Offset(amount, unit) = match offset {
+                    [offset] => Offset::parse(offset)?,
+                    ["a" | "an", unit] => Offset(1, Unit::parse(unit)?),
+                    [amount, unit] => Offset(amount.parse().ok()?, Unit::parse(unit)?),
+                    _ => return None,
+                };
+                let amount = if *dir == "before" { -amount } else { amount };
+                Self::Post(slug, Offset(amount, unit))
+            }
+            _ => return None,
+        };
+        Some(expr)
     }
 
-    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        deserializer.deserialize_any(Visitor::<true>).map(Some)
+    /// `next friday` or `next week`
+    fn relative(shift: i64, word: &str) -> Option<Self> {
+        match word.parse::<Weekday>() {
+            Ok(weekday) => Some(Self::Weekday(shift, weekday)),
+            Err(_) => Some(Self::Today(Offset(shift, Unit::parse(word)?))),
+        }
+    }
+
+    /// The date of the expression, if it doesn't depend on another post.
+    fn eval(self, today: Date) -> Option<Date> {
+        match self {
+            Self::Today(offset) => offset.add_to(today),
+            Self::Weekday(weeks, weekday) => {
+                let ahead = (7 + weekday.num_days_from_monday()
+                    - today.weekday().num_days_from_monday())
+                    % 7;
+                Offset(i64::from(ahead) + weeks * 7, Unit::Day).add_to(today)
+            }
+            Self::Bound(bound, unit, shift) => {
+                let start = match unit {
+                    Unit::Day => today,
+                    Unit::Week => today - Days::new(today.weekday().num_days_from_monday().into()),
+                    Unit::Month => today.with_day(1)?,
+                    Unit::Year => today.with_ordinal(1)?,
+                };
+                let start = Offset(shift, unit).add_to(start)?;
+                match bound {
+                    Bound::Start => Some(start),
+                    Bound::End => Offset(1, unit).add_to(start)?.pred_opt(),
+                }
+            }
+            Self::Post(..) => None,
+        }
+    }
+}
+
+impl Offset {
+    /// Parses an offset like `+3d`, `-2w`, `1m`, or `10days`.
+    fn parse(offset: &str) -> Option<Self> {
+        let (negative, offset) = match offset.as_bytes().first()? {
+            b'+' => (false, &offset[1..]),
+            b'-' => (true, &offset[1..]),
+            _ => (false, offset),
+        };
+        let digits = offset.len()
+            - offset
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let amount = offset[..digits].parse::<i64>().ok()?;
+        let unit = Unit::parse(&offset[digits..])?;
+        Some(Self(if negative { -amount } else { amount }, unit))
     }
 
-    fn visit_unit<E: de::Error>(self) -> Result<Option<PublishDate>, E> {
-        Ok(None)
+    /// This offset `n` times.
+    pub fn times(self, n: i64) -> Option<Self> {
+        Some(Self(self.0.checked_mul(n)?, self.1))
     }
 
-    fn visit_none<E: de::Error>(self) -> Result<Option<PublishDate>, E> {
-        Ok(None)
+    pub fn add_to(self, date: Date) -> Option<Date> {
+        let Self(amount, unit) = self;
+        let (days, months) = match unit {
+            Unit::Day => (amount, 0),
+            Unit::Week => (amount.checked_mul(7)?, 0),
+            Unit::Month => (0, amount),
+            Unit::Year => (0, amount.checked_mul(12)?),
+        };
+        let days = Days::new(days.unsigned_abs());
+        let months = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+        if amount < 0 {
+            date.checked_sub_days(days)?.checked_sub_months(months)
+        } else {
+            date.checked_add_days(days)?.checked_add_months(months)
+        }
     }
 }
 
-impl<'de> de::Visitor<'de> for Visitor<true> {
-    type Value = PublishDate;
+impl FromStr for Offset {
+    type Err = String;
 
-    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        formatter.write_str("an ISO 8601 date")
+    fn from_str(offset: &str) -> Result<Self, Self::Err> {
+        Self::parse(&offset.to_lowercase())
+            .ok_or_else(|| format!("invalid interval '{offset}', expected e.g. 3d, 2w, or 1m"))
     }
+}
 
-    fn visit_str<E: de::Error>(self, value: &str) -> Result<PublishDate, E> {
-        parse(value).map_err(E::custom)
+impl Unit {
+    fn parse(unit: &str) -> Option<Self> {
+        match unit {
+            "d" | "day" | "days" => Some(Self::Day),
+            "w" | "week" | "weeks" => Some(Self::Week),
+            "m" | "month" | "months" => Some(Self::Month),
+            "y" | "year" | "years" => Some(Self::Year),
+            _ => None,
+        }
     }
 }
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn serialize<S: Serializer>(
+    option: &Option<PublishDate>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match option {
+        Some(PublishDate::Date(date)) => serializer.collect_str(&date.format("%Y-%m-%d")),
+        Some(PublishDate::Draft) => serializer.serialize_str(""),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[test]
+fn date_expressions() {
+    let date = |m, d| Date::from_ymd_opt(2015, m, d).unwrap();
+    let eval = |expr| Expr::parse(expr).and_then(|e| e.eval(date(10, 21)));
+    // today is a Wednesday
+    assert_eq!(eval("Today"), Some(date(10, 21)));
+    assert_eq!(eval("tomorrow"), Some(date(10, 22)));
+    assert_eq!(eval("yesterday"), Some(date(10, 20)));
+    assert_eq!(eval("+3d"), Some(date(10, 24)));
+    assert_eq!(eval("-2w"), Some(date(10, 7)));
+    assert_eq!(eval("+1m"), Some(date(11, 21)));
+    assert_eq!(eval("friday"), Some(date(10, 23)));
+    assert_eq!(eval("this wednesday"), Some(date(10, 21)));
+    assert_eq!(eval("next tue"), Some(date(11, 3)));
+    assert_eq!(eval("last monday"), Some(date(10, 19)));
+    assert_eq!(eval("next week"), Some(date(10, 28)));
+    assert_eq!(eval("last month"), Some(date(9, 21)));
+    assert_eq!(eval("end of month"), Some(date(10, 31)));
+    assert_eq!(eval("end of next month"), Some(date(11, 30)));
+    assert_eq!(eval("start of next week"), Some(date(10, 26)));
+    assert_eq!(eval("end of week"), Some(date(10, 25)));
+    assert_eq!(
+        eval("beginning of last year"),
+        Some(Date::from_ymd_opt(2014, 1, 1).unwrap())
+    );
+    assert_eq!(eval("2015-10-21"), None);
+    assert_eq!(eval("in 3 days"), None);
+
+    assert_eq!(
+        Expr::parse("same as My-Post"),
+        Some(Expr::Post("My-Post", Offset(0, Unit::Day)))
+    );
+    assert_eq!(
+        Expr::parse("2 weeks after prev"),
+        Some(Expr::Post("prev", Offset(2, Unit::Week)))
+    );
+    assert_eq!(
+        Expr::parse("a day before prev"),
+        Some(Expr::Post("prev", Offset(-1, Unit::Day)))
+    );
+    assert_eq!(
+        Expr::parse("3d before prev"),
+        Some(Expr::Post("prev", Offset(-3, Unit::Day)))
+    );
+
+    let post_date = |slug: &str| {
+        assert_eq!(slug, "prev");
+        Ok(PublishDate::Date(date(1, 31)))
+    };
+    assert_eq!(
+        parse_with("1 month after prev", None, post_date).unwrap(),
+        PublishDate::Date(date(2, 28))
+    );
+    assert!(parse_with("same as prev", None, |_| Ok(PublishDate::Draft)).is_err());
+    assert!(parse("same as prev", None).is_err());
+    assert!(is_post_relative("2 weeks after prev"));
+    assert!(!is_post_relative("next friday"));
+    assert!(is_absolute("2015-10-21"));
+    assert!(is_absolute(""));
+    assert!(!is_absolute("+7d"));
+    assert!(!is_absolute("next friday"));
+
+    let tz = Some(Tz::Pacific__Kiritimati);
+    assert_eq!(
+        parse_in("2015-10-21", tz).unwrap(),
+        PublishDate::Date(date(10, 21))
+    );
+    assert_eq!(parse_in("draft", tz).unwrap(), PublishDate::Draft);
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use kommandozeile::{
+    color_eyre::eyre::{OptionExt as _, WrapErr as _},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args, blocks,
+    config::{self, Config},
+    dateformat::{self, PublishDate},
+    Post, PostInput,
+};
+
+/// The name of the data that records the expiry date in the body of a post.
+const DATA: &str = "expires";
+
+/// What happens to a post when it expires.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Expired {
+    Draft,
+    Delete,
+}
+
+/// A change to a post that a policy asks for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Change<'a> {
+    post: &'a Post,
+    action: Expired,
+    reason: String,
+}
+
+/// Since when the drafts are unchanged, by slug.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Drafts {
+    drafts: BTreeMap<String, Draft>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Draft {
+    /// A hash of the title and the body of the draft.
+    hash: String,
+    /// The first day on which the draft was seen with this hash.
+    since: String,
+}
+
+/// Records the expiry date of the post in its body.
+pub fn apply_to(post: &mut PostInput) {
+    if let (Some(PublishDate::Date(date)), Some(body)) = (post.expires, &post.body) {
+        let json = serde_json::json!({ "date": date.format("%Y-%m-%d").to_string() });
+        post.body = Some(blocks::set_data(body, DATA, &json.to_string()));
+    }
+}
+
+/// The expiry date that is recorded in the body of `post`, if any.
+fn expires(post: &Post) -> Option<NaiveDate> {
+    let data = blocks::data(post.body.as_deref()?, DATA)?;
+    let data = serde_json::from_str::<serde_json::Value>(data).ok()?;
+    NaiveDate::parse_from_str(data.get("date")?.as_str()?, "%Y-%m-%d").ok()
+}
+
+/// Shows or makes the changes that the retention policies ask for.
+pub fn run(client: &api::Client, opts: Opts, config: &Config, cmd: &args::Expire) -> Result<()> {
+    let policy = &config.expire;
+    let posts = client.all_posts()?;
+    let today = dateformat::today(config.timezone);
+
+    let state = state_path(policy)?;
+    let mut drafts = Drafts::load(&state)?;
+    drafts.update(&posts, today);
+    if !opts.dry_run {
+        drafts.save(&state)?;
+    }
+
+    let changes = plan(&posts, policy, &drafts, today);
+    if changes.is_empty() {
+        println!("No post has expired");
+        return Ok(());
+    }
+    for change in &changes {
+        println!(
+            "{:<6}  {}: {}",
+            change.action, change.post.slug, change.reason
+        );
+    }
+    if !cmd.apply {
+        println!("Run with --apply to make these changes");
+        return Ok(());
+    }
+
+    let backups = backups_dir(policy)?;
+    for change in &changes {
+        let slug = change.post.slug.clone();
+        match change.action {
+            Expired::Draft => api::Update::builder()
+                .slug(slug)
+                .published_at(Some(PublishDate::Draft))
+                .build()
+                .run(client, opts, |_| Ok(()))?,
+            Expired::Delete => {
+                if !opts.dry_run {
+                    let path = backup(&backups, change.post, config.timezone)?;
+                    println!("Backed up {} to {}", change.post.slug, path.display());
+                }
+                api::Delete::builder()
+                    .slug(slug)
+                    .build()
+                    .run(client, opts, |()| Ok(()))?;
+            }
+        }
+    }
+
+    if !opts.dry_run {
+        println!("Changed {} posts", changes.len());
+    }
+    Ok(())
+}
+
+/// The posts that have expired, and the drafts that are unchanged for too long.
+fn plan<'a>(
+    posts: &'a [Post],
+    policy: &config::Expire,
+    drafts: &Drafts,
+    today: NaiveDate,
+) -> Vec<Change<'a>> {
+    let mut changes = Vec::new();
+    for post in posts {
+        let draft = post.publish_date() == PublishDate::Draft;
+
+        if let (Some(action), Some(date)) = (policy.expired, expires(post)) {
+            if date <= today && !(draft && action == Expired::Draft) {
+                changes.push(Change {
+                    post,
+                    action,
+                    reason: format!("expired on {}", date.format("%Y-%m-%d")),
+                });
+                continue;
+            }
+        }
+
+        let stale = policy
+            .stale_drafts
+            .filter(|_| draft)
+            .zip(drafts.since(&post.slug));
+        if let Some((days, since)) = stale {
+            if (today - since).num_days() >= i64::from(days) {
+                changes.push(Change {
+                    post,
+                    action: Expired::Delete,
+                    reason: format!("draft unchanged since {}", since.format("%Y-%m-%d")),
+                });
+            }
+        }
+    }
+    changes
+}
+
+/// Writes `post` as a post file into `dir`, from which it can be created again.
+fn backup(dir: &Path, post: &Post, tz: Option<Tz>) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .wrap_err_with(|| format!("Failed to create the backup directory '{}'", dir.display()))?;
+    let now = dateformat::now(tz).format("%Y%m%dT%H%M%S");
+    let path = dir.join(format!("{}-{now}.md", post.slug));
+    fs::write(&path, post.to_string())
+        .wrap_err_with(|| format!("Failed to back up '{}' to '{}'", post.slug, path.display()))?;
+    Ok(path)
+}
+
+fn backups_dir(policy: &config::Expire) -> Result<PathBuf> {
+    if let Some(dir) = &policy.backups {
+        return Ok(dir.clone());
+    }
+    let dir = dirs::data_dir().ok_or_eyre("There is no data directory for the backups")?;
+    Ok(dir.join("mataroa").join("backups"))
+}
+
+fn state_path(policy: &config::Expire) -> Result<PathBuf> {
+    if let Some(path) = &policy.state {
+        return Ok(path.clone());
+    }
+    let dir = dirs::data_dir().ok_or_eyre("There is no data directory for the drafts")?;
+    Ok(dir.join("mataroa").join("drafts.json"))
+}
+
+impl fmt::Display for Expired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Draft => "draft",
+            Self::Delete => "delete",
+        })
+    }
+}
+
+impl Drafts {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let drafts = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read the drafts '{}'", path.display()))?;
+        serde_json::from_str(&drafts)
+            .wrap_err_with(|| format!("Invalid drafts file '{}'", path.display()))
+    }
+
+    /// Writes the drafts to a temporary file first, so that they are never
+    /// left half-written.
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        let mut drafts = serde_json::to_string_pretty(self)?;
+        drafts.push('\n');
+        fs::write(&tmp, drafts)
+            .and_then(|()| fs::rename(&tmp, path))
+            .wrap_err_with(|| format!("Failed to write the drafts '{}'", path.display()))
+    }
+
+    /// Records the drafts that are new or have changed as unchanged since
+    /// `today`, and forgets the posts that are no drafts anymore.
+    fn update(&mut self, posts: &[Post], today: NaiveDate) {
+        let mut drafts = BTreeMap::new();
+        for post in posts {
+            if post.publish_date() != PublishDate::Draft {
+                continue;
+            }
+            let hash = hash(post);
+            let draft = match self.drafts.remove(&post.slug) {
+                Some(draft) if draft.hash == hash => draft,
+                _ => Draft {
+                    hash,
+                    since: today.format("%Y-%m-%d").to_string(),
+                },
+            };
+            let _ = drafts.insert(post.slug.clone(), draft);
+        }
+        self.drafts = drafts;
+    }
+
+    fn since(&self, slug: &str) -> Option<NaiveDate> {
+        let draft = self.drafts.get(slug)?;
+        NaiveDate::parse_from_str(&draft.since, "%Y-%m-%d").ok()
+    }
+}
+
+/// A 64-bit FNV-1a hash of the title and the body of `post`, which, unlike
+/// the hasher of the standard library, is stable across versions.
+fn hash(post: &Post) -> String {
+    let title = post.title.as_deref().unwrap_or_default();
+    let body = post.body.as_deref().unwrap_or_default();
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in title.bytes().chain([0]).chain(body.bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+#[test]
+fn expiry_dates() {
+    let post = |slug: &str, published_at: &str, expires: Option<&str>| Post {
+        slug: slug.to_owned(),
+        title: None,
+        body: expires
+            .map(|date| blocks::set_data("Body", DATA, &format!("{{\"date\":\"{date}\"}}"))),
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let date = |d| NaiveDate::from_ymd_opt(2015, 10, d).unwrap();
+
+    assert_eq!(
+        expires(&post("expired", "2015-10-01", Some("2015-10-21"))),
+        Some(date(21))
+    );
+    assert_eq!(expires(&post("published", "2015-10-01", None)), None);
+}
+
+#[test]
+fn track_drafts() {
+    let post = |slug: &str, published_at: &str, expires: Option<&str>| Post {
+        slug: slug.to_owned(),
+        title: None,
+        body: expires
+            .map(|date| blocks::set_data("Body", DATA, &format!("{{\"date\":\"{date}\"}}"))),
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let date = |d| NaiveDate::from_ymd_opt(2015, 10, d).unwrap();
+    let posts = [
+        post("expired-draft", "", Some("2015-10-01")),
+        post("draft", "", None),
+        post("published", "2015-10-01", None),
+    ];
+    let mut drafts = Drafts::default();
+    drafts.update(&posts, date(1));
+    assert_eq!(drafts.since("draft"), Some(date(1)));
+    assert_eq!(drafts.since("published"), None);
+
+    let mut edited = posts.clone();
+    edited[1].body = Some(String::from("Changed"));
+    drafts.update(&edited, date(10));
+    assert_eq!(drafts.since("draft"), Some(date(10)));
+    assert_eq!(drafts.since("expired-draft"), Some(date(1)));
+}
+
+#[test]
+fn plan_changes() {
+    let post = |slug: &str, published_at: &str, expires: Option<&str>| Post {
+        slug: slug.to_owned(),
+        title: None,
+        body: expires
+            .map(|date| blocks::set_data("Body", DATA, &format!("{{\"date\":\"{date}\"}}"))),
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let date = |d| NaiveDate::from_ymd_opt(2015, 10, d).unwrap();
+    let posts = [
+        post("expired", "2015-10-01", Some("2015-10-21")),
+        post("expires-later", "2015-10-01", Some("2015-10-22")),
+        post("expired-draft", "", Some("2015-10-01")),
+        post("draft", "", None),
+        post("published", "2015-10-01", None),
+    ];
+    let mut drafts = Drafts::default();
+    drafts.update(&posts, date(1));
+    let policy = config::Expire {
+        expired: Some(Expired::Draft),
+        stale_drafts: Some(20),
+        ..config::Expire::default()
+    };
+    let changes = plan(&posts, &policy, &drafts, date(21))
+        .into_iter()
+        .map(|c| (c.post.slug.as_str(), c.action))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            ("expired", Expired::Draft),
+            ("expired-draft", Expired::Delete),
+            ("draft", Expired::Delete)
+        ]
+    );
+}
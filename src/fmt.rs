@@ -0,0 +1,90 @@
+use std::fs;
+
+use kommandozeile::{
+    color_eyre::eyre::{bail, WrapErr as _},
+    Result,
+};
+
+use crate::{
+    args::{self, Transform},
+    cmark::{self, Wrap},
+    config::Config,
+    frontmatter,
+    transform::{self, Transforms},
+    PostInput,
+};
+
+/// Applies the transforms to the bodies of the given post files, writes
+/// the files in the canonical form, and writes the files that changed.
+/// With `--check`, the files are only reported.
+pub fn run(config: &Config, cmd: &args::Fmt) -> Result<()> {
+    let enabled = |t| cmd.transform.contains(&t).then_some(true);
+    let flags = Transforms {
+        shift_headings: enabled(Transform::ShiftHeadings),
+        toc: enabled(Transform::Toc),
+        footnotes: enabled(Transform::Footnotes),
+        typography: enabled(Transform::Typography),
+    };
+    let wrap = cmd.wrap.unwrap_or(config.fmt.wrap);
+
+    let mut unformatted = 0_usize;
+    for path in &cmd.files {
+        let content = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read '{}'", path.display()))?;
+        // files without a frontmatter code block are not parsed, which would
+        // only warn about the missing frontmatter
+        let transforms = if frontmatter::body_start(&content) == frontmatter::title_end(&content) {
+            flags.or(config.transforms)
+        } else {
+            let post = PostInput::parse(&content, config.timezone)?;
+            flags.or(post.transforms).or(config.transforms)
+        };
+
+        let formatted = format(&content, transforms, wrap)
+            .wrap_err_with(|| format!("Failed to format '{}'", path.display()))?;
+        if formatted == content {
+            continue;
+        }
+
+        if cmd.check {
+            println!("{} is not formatted", path.display());
+            unformatted += 1;
+        } else {
+            fs::write(path, formatted)?;
+            println!("Formatted {}", path.display());
+        }
+    }
+
+    if unformatted > 0 {
+        bail!(
+            "{unformatted} of {} files are not formatted",
+            cmd.files.len()
+        );
+    }
+    Ok(())
+}
+
+fn format(content: &str, transforms: Transforms, wrap: Wrap) -> Result<String> {
+    let title_end = frontmatter::title_end(content);
+    let body = transform::apply(
+        content[frontmatter::body_start(content)..].trim(),
+        transforms,
+    );
+
+    // the title and the body are formatted together, since the title can
+    // use reference definitions from the body
+    let document = cmark::format(&format!("{}\n\n{body}", &content[..title_end]), wrap);
+    let (title, body) = if title_end == 0 {
+        ("", document.as_str())
+    } else {
+        document.split_at(document.find('\n').map_or(document.len(), |nl| nl + 1))
+    };
+    let frontmatter = frontmatter::format(content)?;
+
+    let formatted = [title, frontmatter.as_str(), body.trim_start_matches('\n')]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(formatted)
+}
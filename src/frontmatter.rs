@@ -0,0 +1,420 @@
+use std::{fmt::Write as _, fs, ops::Range, path::Path};
+
+use kommandozeile::{color_eyre::eyre::bail, Result};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+
+use crate::Post;
+
+/// Keys that come first in a formatted frontmatter, in this order.
+/// All other keys follow in alphabetical order.
+const KEY_ORDER: [&str; 4] = ["title", "slug", "published_at", "url"];
+
+/// Records the slug, publish date, and URL of `post` in the frontmatter
+/// of the post file at `path`.
+pub fn write_back(path: &Path, post: &Post) -> Result<()> {
+    let published_at = post.published_at.as_deref().unwrap_or_default();
+    let mut fields = vec![("slug", post.slug.as_str()), ("published_at", published_at)];
+    if let Some(url) = &post.url {
+        fields.push(("url", url));
+    }
+
+    let content = fs::read_to_string(path)?;
+    let content = set(&content, &fields)?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Sets the given keys in the frontmatter of the post file `content`.
+///
+/// Existing keys are replaced, missing keys are added, and everything else
+/// in the file is preserved verbatim.
+/// If the file has no frontmatter yet, a yaml frontmatter is inserted after
+/// the leading h1 or at the start of the file.
+pub fn set(content: &str, fields: &[(&str, &str)]) -> Result<String> {
+    let block = find(content);
+
+    let Some(block) = block.code_block else {
+        let mut frontmatter = String::from("```yml\n");
+        for (key, value) in fields {
+            frontmatter.push_str(&yaml_line(key, value));
+        }
+        frontmatter.push_str("```\n\n");
+
+        let at = block.title_end;
+        let (head, tail) = content.split_at(at);
+        let head = if head.is_empty() || head.ends_with("\n\n") {
+            head.to_owned()
+        } else if head.ends_with('\n') {
+            format!("{head}\n")
+        } else {
+            format!("{head}\n\n")
+        };
+        return Ok(format!(
+            "{head}{frontmatter}{}",
+            tail.trim_start_matches('\n')
+        ));
+    };
+
+    let source = &content[block.content.clone()];
+    let source = match block.lang {
+        Lang::Yaml => set_yaml(source, fields),
+        Lang::Json => set_json(source, fields)?,
+    };
+
+    let mut result = String::with_capacity(content.len() + source.len());
+    result.push_str(&content[..block.content.start]);
+    result.push_str(&source);
+    result.push_str(&content[block.content.end..]);
+    Ok(result)
+}
+
+/// The offset in the post file `content` where the body starts,
+/// after the leading h1 and the frontmatter.
+pub fn body_start(content: &str) -> usize {
+    find(content).end
+}
+
+/// The offset in the post file `content` after the leading h1,
+/// or 0 if there is none.
+pub fn title_end(content: &str) -> usize {
+    find(content).title_end
+}
+
+/// The frontmatter code block of the post file `content` in its canonical
+/// form, or an empty string if there is none.
+///
+/// The keys are ordered, strings are double quoted, and the fence is
+/// always `yml` or `json`. Comments in a yaml frontmatter are not kept.
+pub fn format(content: &str) -> Result<String> {
+    let mut head = String::new();
+    let Some(block) = find(content).code_block else {
+        return Ok(head);
+    };
+
+    let source = &content[block.content];
+    match block.lang {
+        Lang::Yaml => {
+            let value = serde_yaml::from_str::<serde_yaml::Value>(source)?;
+            head.push_str("```yml\n");
+            match value {
+                serde_yaml::Value::Null => {}
+                serde_yaml::Value::Mapping(mapping) => format_yaml(&mut head, &mapping, 0)?,
+                _ => bail!("The yaml frontmatter must be a mapping"),
+            }
+        }
+        Lang::Json => {
+            let value = if source.trim().is_empty() {
+                serde_json::Value::Object(serde_json::Map::new())
+            } else {
+                serde_json::from_str::<serde_json::Value>(source)?
+            };
+            let Some(object) = value.as_object() else {
+                bail!("The json frontmatter must be an object");
+            };
+            head.push_str("```json\n");
+            format_json(&mut head, object)?;
+        }
+    }
+    head.push_str("```\n");
+    Ok(head)
+}
+
+/// The frontmatter of the post file `content` as a map, changed by `edit`,
+/// as a code block in its canonical form (see [`format`]).
+/// The block is json if the file has a json frontmatter, and yaml otherwise.
+pub fn rewrite(
+    content: &str,
+    edit: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>),
+) -> Result<String> {
+    let block = find(content).code_block;
+    let source = block.as_ref().map_or("", |b| &content[b.content.clone()]);
+    let value = match block.as_ref().map(|b| b.lang) {
+        _ if source.trim().is_empty() => serde_json::Value::Null,
+        Some(Lang::Json) => serde_json::from_str(source)?,
+        _ => serde_yaml::from_str(source)?,
+    };
+    let mut object = match value {
+        serde_json::Value::Null => serde_json::Map::new(),
+        serde_json::Value::Object(object) => object,
+        _ => bail!("The frontmatter must be a mapping"),
+    };
+    edit(&mut object);
+
+    let mut head = String::new();
+    if block.is_some_and(|b| b.lang == Lang::Json) {
+        head.push_str("```json\n");
+        format_json(&mut head, &object)?;
+    } else {
+        let serde_yaml::Value::Mapping(mapping) = serde_yaml::to_value(&object)? else {
+            unreachable!("a json object is a yaml mapping");
+        };
+        head.push_str("```yml\n");
+        format_yaml(&mut head, &mapping, 0)?;
+    }
+    head.push_str("```\n");
+    Ok(head)
+}
+
+fn ordered<'a, V>(entries: impl IntoIterator<Item = (&'a str, V)>) -> Vec<(&'a str, V)> {
+    let mut entries = entries.into_iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(key, _)| {
+        KEY_ORDER
+            .iter()
+            .position(|k| k == key)
+            .map_or((KEY_ORDER.len(), *key), |pos| (pos, ""))
+    });
+    entries
+}
+
+fn format_yaml(out: &mut String, mapping: &serde_yaml::Mapping, indent: usize) -> Result<()> {
+    let entries = mapping
+        .iter()
+        .map(|(key, value)| match key {
+            serde_yaml::Value::String(key) => Ok((key.as_str(), value)),
+            _ => bail!("The frontmatter keys must be strings"),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for (key, value) in ordered(entries) {
+        out.push_str(&" ".repeat(indent));
+        if key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        {
+            out.push_str(key);
+        } else {
+            out.push_str(&serde_json::to_string(key)?);
+        }
+        match value {
+            serde_yaml::Value::Mapping(mapping) if !mapping.is_empty() => {
+                out.push_str(":\n");
+                format_yaml(out, mapping, indent + 2)?;
+            }
+            // json is also valid yaml and quotes all strings
+            value => {
+                out.push_str(": ");
+                out.push_str(&serde_json::to_string(value)?);
+                out.push('\n');
+            }
+        }
+    }
+    Ok(())
+}
+
+fn format_json(
+    out: &mut String,
+    object: &serde_json::Map<String, serde_json::Value>,
+) -> Result<()> {
+    out.push_str("{\n");
+    let entries = ordered(object.iter().map(|(k, v)| (k.as_str(), v)));
+    let count = entries.len();
+    for (idx, (key, value)) in entries.into_iter().enumerate() {
+        let value = serde_json::to_string_pretty(value)?.replace('\n', "\n  ");
+        let comma = if idx + 1 < count { "," } else { "" };
+        let _ = writeln!(out, "  {}: {value}{comma}", serde_json::Value::from(key));
+    }
+    out.push_str("}\n");
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Found {
+    /// Offset after the leading h1, or 0 if there is none.
+    title_end: usize,
+    /// Offset after the frontmatter, or after the h1 if there is none.
+    end: usize,
+    code_block: Option<CodeBlock>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CodeBlock {
+    lang: Lang,
+    /// The range of the frontmatter source, without the fences.
+    content: Range<usize>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Lang {
+    Yaml,
+    Json,
+}
+
+/// Locates the frontmatter the same way that `PostInput::from_markdown` does:
+/// A code block at the start of the document, optionally after an h1.
+fn find(content: &str) -> Found {
+    let mut events = Parser::new_ext(content, Options::all()).into_offset_iter();
+    let mut found = Found {
+        title_end: 0,
+        end: 0,
+        code_block: None,
+    };
+
+    let mut next = events.next();
+    if let Some((Event::Start(Tag::Heading(HeadingLevel::H1, ..)), range)) = &next {
+        found.title_end = range.end;
+        next = events.next();
+        while let Some((event, range)) = next {
+            found.title_end = range.end;
+            next = events.next();
+            if matches!(event, Event::End(Tag::Heading(..))) {
+                break;
+            }
+        }
+    }
+
+    found.end = found.title_end;
+    let Some((Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))), range)) = next else {
+        return found;
+    };
+    found.end = range.end;
+
+    let lang = if lang.as_ref() == "json" {
+        Lang::Json
+    } else {
+        Lang::Yaml
+    };
+
+    // the source starts on the line after the opening fence
+    let start = content[range.clone()]
+        .find('\n')
+        .map_or(range.end, |nl| range.start + nl + 1);
+    let mut end = start;
+    for (event, range) in events {
+        match event {
+            Event::Text(_) => end = range.end,
+            _ => break,
+        }
+    }
+
+    found.code_block = Some(CodeBlock {
+        lang,
+        content: start..end,
+    });
+    found
+}
+
+fn set_yaml(source: &str, fields: &[(&str, &str)]) -> String {
+    let mut lines = source.lines().map(str::to_owned).collect::<Vec<_>>();
+
+    for (key, value) in fields {
+        let line = yaml_line(key, value);
+        let line = line.trim_end();
+        let existing = lines.iter().position(|l| {
+            l.strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with(':'))
+        });
+        match existing {
+            Some(at) => {
+                let end = at + 1 + continuation(&lines[at + 1..]);
+                let _ = lines.splice(at..end, [line.to_owned()]);
+            }
+            None => lines.push(line.to_owned()),
+        }
+    }
+
+    let mut source = lines.join("\n");
+    source.push('\n');
+    source
+}
+
+/// The number of lines at the start of `lines` that continue the value of the
+/// key before them, e.g. a block scalar, a nested mapping, or a sequence.
+fn continuation(lines: &[String]) -> usize {
+    let mut count = 0;
+    for (i, line) in lines.iter().enumerate() {
+        if line.starts_with([' ', '\t']) || line == "-" || line.starts_with("- ") {
+            count = i + 1;
+        } else if !line.trim().is_empty() {
+            break;
+        }
+    }
+    count
+}
+
+fn set_json(source: &str, fields: &[(&str, &str)]) -> Result<String> {
+    let mut value = if source.trim().is_empty() {
+        serde_json::Value::Object(serde_json::Map::new())
+    } else {
+        serde_json::from_str::<serde_json::Value>(source)?
+    };
+
+    let Some(object) = value.as_object_mut() else {
+        bail!("The json frontmatter must be an object");
+    };
+
+    for (key, value) in fields {
+        let _ = object.insert((*key).to_owned(), (*value).into());
+    }
+
+    let mut source = serde_json::to_string_pretty(&value)?;
+    source.push('\n');
+    Ok(source)
+}
+
+fn yaml_line(key: &str, value: &str) -> String {
+    // a json string is also a valid double quoted yaml string
+    let value = serde_json::Value::from(value);
+    format!("{key}: {value}\n")
+}
+
+#[test]
+fn set_frontmatter_fields() {
+    let post = "# Title\n\n```yml\nslug: old\npublished_at: \"\"\n```\n\nBody\n";
+    assert_eq!(
+        set(post, &[("slug", "new"), ("url", "https://x")]).unwrap(),
+        "# Title\n\n```yml\nslug: \"new\"\npublished_at: \"\"\nurl: \"https://x\"\n```\n\nBody\n"
+    );
+
+    let post = "# Title\n\nBody\n";
+    assert_eq!(
+        set(post, &[("slug", "new")]).unwrap(),
+        "# Title\n\n```yml\nslug: \"new\"\n```\n\nBody\n"
+    );
+
+    let post = concat!(
+        "```yml\n",
+        "summary: |\n  first\n\n  second\n",
+        "tags:\n- a\n- b\n",
+        "vars:\n  a: 1\n\n",
+        "slug: old\n",
+        "```\n",
+    );
+    assert_eq!(
+        set(post, &[("summary", "new"), ("tags", "c"), ("vars", "v")]).unwrap(),
+        "```yml\nsummary: \"new\"\ntags: \"c\"\nvars: \"v\"\n\nslug: old\n```\n"
+    );
+}
+
+#[test]
+fn format_frontmatter() {
+    let post = concat!(
+        "Title\n=====\n\n",
+        "```yaml\n",
+        "# a comment\n",
+        "vars: {b: 2, a: one}\n",
+        "url:   https://x\n",
+        "published_at: 2015-10-21\n",
+        "title: 'Other'\n",
+        "```\n\nBody\n",
+    );
+    assert_eq!(
+        format(post).unwrap(),
+        concat!(
+            "```yml\n",
+            "title: \"Other\"\n",
+            "published_at: \"2015-10-21\"\n",
+            "url: \"https://x\"\n",
+            "vars:\n",
+            "  a: \"one\"\n",
+            "  b: 2\n",
+            "```\n",
+        )
+    );
+
+    let post =
+        "```json\n{\"url\": \"https://x\", \"vars\": {\"a\": \"b\"}, \"slug\": \"s\"}\n```\n";
+    assert_eq!(
+        format(post).unwrap(),
+        "```json\n{\n  \"slug\": \"s\",\n  \"url\": \"https://x\",\n  \"vars\": {\n    \"a\": \"b\"\n  }\n}\n```\n"
+    );
+}
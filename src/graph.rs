@@ -0,0 +1,255 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+};
+
+use kommandozeile::{OutputFile, Result};
+use serde::Serialize;
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args::{self, GraphFormat},
+    blocks,
+    links::{self, Blog},
+    transform, write_output, Post,
+};
+
+/// The name of the generated backlinks block.
+const BACKLINKS: &str = "backlinks";
+
+/// The links between the posts of a blog.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Graph<'a> {
+    posts: Vec<&'a Post>,
+    /// Pairs of slugs (from, to), without self-links and links to missing posts.
+    edges: BTreeSet<(&'a str, &'a str)>,
+}
+
+impl<'a> Graph<'a> {
+    fn new(posts: &'a [Post], blog: &Blog) -> Self {
+        let slugs = posts
+            .iter()
+            .map(|p| p.slug.as_str())
+            .collect::<BTreeSet<_>>();
+
+        let mut edges = BTreeSet::new();
+        for post in posts {
+            let Some(body) = post.body.as_deref() else {
+                continue;
+            };
+            // the generated backlinks are not links made by the author
+            let body = blocks::remove(body, BACKLINKS);
+            for link in links::find(&body, blog) {
+                if let Some(to) = slugs.get(link.slug.as_str()) {
+                    if *to != post.slug {
+                        let _ = edges.insert((post.slug.as_str(), *to));
+                    }
+                }
+            }
+        }
+
+        Self {
+            posts: posts.iter().collect(),
+            edges,
+        }
+    }
+
+    /// The posts that link to the post with the given slug.
+    fn linked_from(&self, slug: &str) -> Vec<&'a Post> {
+        self.posts
+            .iter()
+            .copied()
+            .filter(|post| self.edges.contains(&(post.slug.as_str(), slug)))
+            .collect()
+    }
+
+    fn orphans(&self) -> impl Iterator<Item = &'a Post> + '_ {
+        self.posts
+            .iter()
+            .copied()
+            .filter(|post| !self.edges.iter().any(|(_, to)| *to == post.slug))
+    }
+
+    fn dot(&self) -> String {
+        let mut out = String::from("digraph blog {\n");
+        for post in &self.posts {
+            let _ = writeln!(
+                out,
+                "  {} [label={}];",
+                quote(&post.slug),
+                quote(post.title.as_deref().unwrap_or(&post.slug))
+            );
+        }
+        for (from, to) in &self.edges {
+            let _ = writeln!(out, "  {} -> {};", quote(from), quote(to));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn mermaid(&self) -> String {
+        // slugs are not necessarily valid mermaid ids, so number the posts instead
+        let ids = self
+            .posts
+            .iter()
+            .enumerate()
+            .map(|(idx, post)| (post.slug.as_str(), format!("p{idx}")))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut out = String::from("graph LR\n");
+        for post in &self.posts {
+            let title = post.title.as_deref().unwrap_or(&post.slug);
+            let _ = writeln!(
+                out,
+                "  {}[\"{}\"]",
+                ids[post.slug.as_str()],
+                title.replace('"', "#quot;")
+            );
+        }
+        for (from, to) in &self.edges {
+            let _ = writeln!(out, "  {} --> {}", ids[from], ids[to]);
+        }
+        out
+    }
+
+    fn json(&self) -> Result<String> {
+        #[derive(Serialize)]
+        struct Node<'a> {
+            slug: &'a str,
+            title: Option<&'a str>,
+            url: Option<&'a str>,
+        }
+
+        #[derive(Serialize)]
+        struct Edge<'a> {
+            from: &'a str,
+            to: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct Json<'a> {
+            nodes: Vec<Node<'a>>,
+            edges: Vec<Edge<'a>>,
+        }
+
+        let json = Json {
+            nodes: self
+                .posts
+                .iter()
+                .map(|p| Node {
+                    slug: &p.slug,
+                    title: p.title.as_deref(),
+                    url: p.url.as_deref(),
+                })
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .map(|(from, to)| Edge { from, to })
+                .collect(),
+        };
+
+        let mut out = serde_json::to_string_pretty(&json)?;
+        out.push('\n');
+        Ok(out)
+    }
+}
+
+/// Builds the graph of links between all posts and exports it, lists the
+/// orphaned posts, or updates the backlinks in every post.
+pub fn run(client: &api::Client, opts: Opts, cmd: args::Graph) -> Result<()> {
+    let posts = client.all_posts()?;
+    let blog = Blog::new(&posts, cmd.domain.iter().map(String::as_str));
+    let graph = Graph::new(&posts, &blog);
+
+    if cmd.backlinks {
+        return backlinks(client, opts, &graph);
+    }
+
+    let output = cmd.output.unwrap_or(OutputFile::Stdout(None));
+
+    if cmd.orphans {
+        let mut out = String::new();
+        for post in graph.orphans() {
+            let _ = writeln!(out, "{}", post.slug);
+        }
+        return write_output(output, out);
+    }
+
+    let out = match cmd.format {
+        GraphFormat::Dot => graph.dot(),
+        GraphFormat::Mermaid => graph.mermaid(),
+        GraphFormat::Json => graph.json()?,
+    };
+    write_output(output, out)
+}
+
+/// Adds or refreshes the "Linked from" section at the end of every post.
+/// Only posts whose body actually changes are updated.
+fn backlinks(client: &api::Client, opts: Opts, graph: &Graph<'_>) -> Result<()> {
+    let mut updated = 0_usize;
+
+    for post in &graph.posts {
+        let Some(body) = post.body.as_deref() else {
+            continue;
+        };
+
+        let mut section = String::new();
+        for from in graph.linked_from(&post.slug) {
+            if section.is_empty() {
+                section.push_str("## Linked from\n\n");
+            }
+            let title = from.title.as_deref().unwrap_or(&from.slug);
+            let url = from
+                .url
+                .clone()
+                .unwrap_or_else(|| format!("/blog/{}/", from.slug));
+            let _ = writeln!(section, "- [{}]({url})", transform::link_text(title));
+        }
+
+        let new_body = blocks::replace(body, BACKLINKS, &section);
+        if new_body == body {
+            continue;
+        }
+
+        api::Update::builder()
+            .slug(post.slug.clone())
+            .body(Some(new_body))
+            .build()
+            .run(client, opts, |_| {
+                println!("Updated backlinks of {}", post.slug);
+                Ok(())
+            })?;
+        updated += 1;
+    }
+
+    if !opts.dry_run {
+        println!("Updated backlinks of {updated} posts");
+    }
+    Ok(())
+}
+
+/// Quotes `text` as a DOT string, in which only quotes, backslashes, and line
+/// breaks need to be escaped.
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[test]
+fn quote_dot_strings() {
+    assert_eq!(quote("post"), "\"post\"");
+    assert_eq!(quote("Say \"hi\" \\ bye"), "\"Say \\\"hi\\\" \\\\ bye\"");
+    assert_eq!(quote("Two\nlines"), "\"Two\\nlines\"");
+    assert_eq!(quote("👩\u{200d}💻 Café"), "\"👩\u{200d}💻 Café\"");
+}
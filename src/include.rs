@@ -0,0 +1,186 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use kommandozeile::{
+    color_eyre::eyre::{bail, eyre, WrapErr as _},
+    Result,
+};
+
+use crate::watcher;
+
+// Content of other files can be included in a post body with
+//
+// {{#include path/to/file.md}}      the whole file
+// {{#include code.rs:10:20}}        lines 10 to 20
+// {{#include code.rs:10}}           only line 10
+// {{#include code.rs:10:}}          line 10 to the end
+// {{#include code.rs::20}}          the first 20 lines
+// {{#include code.rs:name}}         the lines between `ANCHOR: name` and `ANCHOR_END: name`
+//
+// like mdBook does it. Paths are relative to the file that contains the
+// directive, and included markdown files are expanded as well.
+// A directive is kept as it is when it is escaped as `\{{#include ...}}`.
+
+const DIRECTIVE: &str = "{{#include";
+
+/// Expands all include directives in `body`, which is the body of the post
+/// file at `file` (or of a post read from stdin, if there is no file).
+pub fn expand(body: &str, file: Option<&Path>) -> Result<String> {
+    if !body.contains(DIRECTIVE) {
+        return Ok(body.to_owned());
+    }
+
+    let mut stack = file
+        .and_then(|f| fs::canonicalize(f).ok())
+        .into_iter()
+        .collect::<Vec<_>>();
+    let dir = file
+        .and_then(Path::parent)
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    expand_in(body, dir, &mut stack)
+}
+
+/// `stack` holds the files that are currently being expanded, to detect cycles.
+fn expand_in(text: &str, dir: &Path, stack: &mut Vec<PathBuf>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(DIRECTIVE) {
+        if rest[..start].ends_with('\\') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str(DIRECTIVE);
+            rest = &rest[start + DIRECTIVE.len()..];
+            continue;
+        }
+
+        out.push_str(&rest[..start]);
+        let directive = &rest[start + DIRECTIVE.len()..];
+        let Some(end) = directive.find("}}") else {
+            bail!(
+                "Unclosed include directive: {}",
+                rest[start..].lines().next().unwrap_or_default()
+            );
+        };
+        rest = &directive[end + 2..];
+
+        let (path, selector) = match directive[..end].trim().split_once(':') {
+            Some((path, selector)) => (path, Some(selector)),
+            None => (directive[..end].trim(), None),
+        };
+        out.push_str(&include(&dir.join(path), selector, stack)?);
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn include(path: &Path, selector: Option<&str>, stack: &mut Vec<PathBuf>) -> Result<String> {
+    let canonical = fs::canonicalize(path)
+        .wrap_err_with(|| format!("Failed to include '{}'", path.display()))?;
+    if stack.contains(&canonical) {
+        let cycle = stack
+            .iter()
+            .chain([&canonical])
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>();
+        bail!("Include cycle: {}", cycle.join(" -> "));
+    }
+
+    let content = fs::read_to_string(&canonical)
+        .wrap_err_with(|| format!("Failed to include '{}'", path.display()))?;
+    let content = select(&content, selector)
+        .wrap_err_with(|| format!("Failed to include '{}'", path.display()))?;
+
+    if !watcher::is_post_file(path) {
+        return Ok(content);
+    }
+
+    let dir = canonical.parent().unwrap_or_else(|| Path::new("."));
+    stack.push(canonical.clone());
+    let expanded = expand_in(&content, dir, stack);
+    let _ = stack.pop();
+    expanded
+}
+
+/// The lines of `content` that are selected by a line range or an anchor,
+/// without any anchor lines.
+fn select(content: &str, selector: Option<&str>) -> Result<String> {
+    let lines = content.lines().collect::<Vec<_>>();
+
+    let selected = match selector.map(str::trim) {
+        None | Some("") => &lines[..],
+        Some(selector) if selector.starts_with(|c: char| c.is_ascii_digit() || c == ':') => {
+            let (start, end) = if let Some((start, end)) = selector.split_once(':') {
+                (line_number(start)?, line_number(end)?)
+            } else {
+                let line = line_number(selector)?;
+                (line, line)
+            };
+            let start = start.unwrap_or(1).max(1) - 1;
+            let end = end.unwrap_or(lines.len()).min(lines.len());
+            lines.get(start..end).unwrap_or_default()
+        }
+        Some(name) => {
+            let start = lines
+                .iter()
+                .position(|l| anchor(l, "ANCHOR:") == Some(name))
+                .ok_or_else(|| eyre!("There is no anchor '{name}'"))?;
+            let end = lines[start..]
+                .iter()
+                .position(|l| anchor(l, "ANCHOR_END:") == Some(name))
+                .map_or(lines.len(), |end| start + end);
+            &lines[start + 1..end]
+        }
+    };
+
+    let mut out = String::new();
+    for line in selected {
+        if anchor(line, "ANCHOR:").is_none() && anchor(line, "ANCHOR_END:").is_none() {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    // the directive is replaced inline, the line break after it is kept
+    let _ = out.pop();
+    Ok(out)
+}
+
+fn line_number(number: &str) -> Result<Option<usize>> {
+    let number = number.trim();
+    if number.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(number.parse().wrap_err_with(|| {
+        format!("'{number}' is not a line number")
+    })?))
+}
+
+/// The name of the anchor in `line`, if it contains the given `marker`.
+fn anchor<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let (_, rest) = line.split_once(marker)?;
+    let rest = rest.trim_start();
+    let end = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(rest.len());
+    Some(&rest[..end]).filter(|name| !name.is_empty())
+}
+
+#[test]
+fn select_lines_and_anchors() {
+    let content = "one\ntwo\n// ANCHOR: middle\nthree\nfour\n// ANCHOR_END: middle\nfive\n";
+
+    assert_eq!(
+        select(content, None).unwrap(),
+        "one\ntwo\nthree\nfour\nfive"
+    );
+    assert_eq!(select(content, Some("2")).unwrap(), "two");
+    assert_eq!(select(content, Some("4:5")).unwrap(), "three\nfour");
+    assert_eq!(select(content, Some(":2")).unwrap(), "one\ntwo");
+    assert_eq!(select(content, Some("6:")).unwrap(), "five");
+    assert_eq!(select(content, Some("middle")).unwrap(), "three\nfour");
+    assert!(select(content, Some("missing")).is_err());
+}
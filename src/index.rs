@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike as _, NaiveDate};
+use kommandozeile::{
+    color_eyre::eyre::{OptionExt as _, WrapErr as _},
+    Result,
+};
+use serde::Deserialize;
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args, blocks,
+    config::Config,
+    dateformat::{self, PublishDate},
+    template, transform, Post,
+};
+
+/// The name of the generated index block.
+const INDEX: &str = "index";
+
+/// How the posts in the index are grouped.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Year,
+    #[default]
+    Month,
+}
+
+/// Renders the list of all published posts into the body of the index post,
+/// and updates the post if the list has changed.
+pub fn run(client: &api::Client, opts: Opts, config: &Config, cmd: &args::Index) -> Result<()> {
+    let slug = cmd
+        .slug
+        .as_deref()
+        .or(config.index.slug.as_deref())
+        .ok_or_eyre(
+            "Give the slug of the index post with --slug or as `index.slug` in the config",
+        )?;
+    let group_by = cmd.group_by.unwrap_or(config.index.group_by);
+
+    let posts = client.call(api::List {})?;
+    let index = client
+        .call(api::Get::builder().slug(slug.to_owned()).build())
+        .wrap_err_with(|| format!("Failed to get the index post '{slug}'"))?;
+
+    let list = render(
+        &posts,
+        slug,
+        group_by,
+        config,
+        dateformat::today(config.timezone),
+    )?;
+    let body = index.body.as_deref().unwrap_or_default();
+    let new_body = blocks::replace(body, INDEX, &list);
+    if new_body == body {
+        println!("The index {slug} is up to date");
+        return Ok(());
+    }
+
+    api::Update::builder()
+        .slug(slug.to_owned())
+        .body(Some(new_body))
+        .build()
+        .run(client, opts, |_| {
+            println!("Updated the index {slug}");
+            Ok(())
+        })
+}
+
+/// The published posts except the index post itself, newest first, under a
+/// heading for every year or month.
+fn render(
+    posts: &[Post],
+    slug: &str,
+    group_by: GroupBy,
+    config: &Config,
+    today: NaiveDate,
+) -> Result<String> {
+    let mut published = posts
+        .iter()
+        .filter(|post| post.slug != slug)
+        .filter_map(|post| match post.publish_date() {
+            PublishDate::Date(date) if date <= today => Some((date, post)),
+            PublishDate::Date(_) | PublishDate::Draft => None,
+        })
+        .collect::<Vec<_>>();
+    published.sort_by(|(a, p), (b, q)| b.cmp(a).then_with(|| p.slug.cmp(&q.slug)));
+
+    let heading = config.index.heading.as_deref().unwrap_or(match group_by {
+        GroupBy::Year => "## {{ year }}",
+        GroupBy::Month => "## {{ date | date(\"%B %Y\") }}",
+    });
+
+    let mut out = String::new();
+    let mut group = None;
+    for (date, post) in published {
+        let first = match group_by {
+            GroupBy::Year => date.with_ordinal(1),
+            GroupBy::Month => date.with_day(1),
+        }
+        .unwrap_or(date);
+
+        if group != Some(first) {
+            if group.is_some() {
+                out.push('\n');
+            }
+            let vars = BTreeMap::from([
+                (String::from("year"), first.format("%Y").to_string()),
+                (String::from("month"), first.format("%m").to_string()),
+                (String::from("date"), first.format("%Y-%m-%d").to_string()),
+            ]);
+            out.push_str(&template::render(heading, &vars, config)?);
+            out.push_str("\n\n");
+            group = Some(first);
+        }
+
+        let url = post
+            .url
+            .clone()
+            .unwrap_or_else(|| format!("/blog/{}/", post.slug));
+        let vars = BTreeMap::from([
+            (
+                String::from("title"),
+                transform::link_text(post.title.as_deref().unwrap_or(&post.slug)),
+            ),
+            (String::from("slug"), post.slug.clone()),
+            (String::from("url"), url),
+            (
+                String::from("published_at"),
+                date.format("%Y-%m-%d").to_string(),
+            ),
+        ]);
+        out.push_str(&template::render(&config.index.item, &vars, config)?);
+        out.push('\n');
+    }
+
+    Ok(out.trim_end().to_owned())
+}
+
+#[test]
+fn render_index_by_month() {
+    let post = |slug: &str, published_at: &str| Post {
+        slug: slug.to_owned(),
+        title: Some(slug.to_uppercase()),
+        body: None,
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let posts = [
+        post("old", "2015-09-30"),
+        post("new", "2015-10-21"),
+        post("newer", "2015-10-21"),
+        post("archive", "2015-10-01"),
+        post("future", "2015-10-22"),
+        post("draft", ""),
+    ];
+    let today = NaiveDate::from_ymd_opt(2015, 10, 21).unwrap();
+    let config = Config::default();
+    assert_eq!(
+        render(&posts, "archive", GroupBy::Month, &config, today).unwrap(),
+        concat!(
+            "## October 2015\n\n",
+            "- 2015-10-21: [NEW](/blog/new/)\n",
+            "- 2015-10-21: [NEWER](/blog/newer/)\n\n",
+            "## September 2015\n\n",
+            "- 2015-09-30: [OLD](/blog/old/)",
+        )
+    );
+}
+
+#[test]
+fn render_index_by_year() {
+    let post = |slug: &str, published_at: &str| Post {
+        slug: slug.to_owned(),
+        title: Some(slug.to_uppercase()),
+        body: None,
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let posts = [
+        post("old", "2015-09-30"),
+        post("new", "2015-10-21"),
+        post("newer", "2015-10-21"),
+        post("archive", "2015-10-01"),
+        post("future", "2015-10-22"),
+        post("draft", ""),
+    ];
+    let today = NaiveDate::from_ymd_opt(2015, 10, 21).unwrap();
+    let mut config = Config::default();
+    config.index.item = String::from("- [{{ title | lower }}]({{ url }})");
+    assert_eq!(
+        render(&posts, "archive", GroupBy::Year, &config, today).unwrap(),
+        "## 2015\n\n- [new](/blog/new/)\n- [newer](/blog/newer/)\n- [old](/blog/old/)",
+    );
+}
+
+#[test]
+fn render_index_escapes_titles() {
+    let posts = [Post {
+        slug: String::from("brackets"),
+        title: Some(String::from("[Old] and [new]")),
+        body: None,
+        published_at: Some(String::from("2014-01-01")),
+        url: None,
+    }];
+    let today = NaiveDate::from_ymd_opt(2015, 10, 21).unwrap();
+    assert_eq!(
+        render(&posts, "archive", GroupBy::Year, &Config::default(), today).unwrap(),
+        "## 2014\n\n- 2014-01-01: [\\[Old\\] and \\[new\\]](/blog/brackets/)",
+    );
+}
@@ -0,0 +1,318 @@
+use std::io::{self, BufRead as _, IsTerminal as _, Write as _};
+
+use chrono::NaiveDate;
+use kommandozeile::{
+    color_eyre::eyre::{bail, ensure},
+    tracing::error,
+    Result,
+};
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args::{self, Select},
+    config::Config,
+    dateformat::{self, PublishDate},
+    series, Post,
+};
+
+/// A post whose date is changed, with its date before and after the change.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Change<'a> {
+    post: &'a Post,
+    before: PublishDate,
+    after: PublishDate,
+}
+
+/// Publishes the selected drafts and scheduled posts today.
+pub fn publish(
+    client: &api::Client,
+    opts: Opts,
+    config: &Config,
+    cmd: &args::Publish,
+) -> Result<()> {
+    let today = dateformat::today(config.timezone);
+    change(
+        client,
+        opts,
+        config,
+        &cmd.select,
+        cmd.yes,
+        |before| match before {
+            PublishDate::Date(date) if date <= today => before,
+            PublishDate::Date(_) | PublishDate::Draft => PublishDate::Date(today),
+        },
+    )
+}
+
+/// Turns the selected posts into drafts.
+pub fn unpublish(
+    client: &api::Client,
+    opts: Opts,
+    config: &Config,
+    cmd: &args::Unpublish,
+) -> Result<()> {
+    change(client, opts, config, &cmd.select, cmd.yes, |_| {
+        PublishDate::Draft
+    })
+}
+
+/// Moves the selected posts to the date of `--to`.
+pub fn reschedule(
+    client: &api::Client,
+    opts: Opts,
+    config: &Config,
+    cmd: &args::Reschedule,
+) -> Result<()> {
+    let to = crate::parse_date(client, config, &cmd.to)?;
+    change(client, opts, config, &cmd.select, cmd.yes, |_| to)
+}
+
+/// Shows the dates of the selected posts before and after the change, asks
+/// for confirmation, and changes the posts one after the other.
+fn change(
+    client: &api::Client,
+    opts: Opts,
+    config: &Config,
+    select: &Select,
+    yes: bool,
+    to: impl Fn(PublishDate) -> PublishDate,
+) -> Result<()> {
+    let posts = if select.series.is_some() {
+        client.all_posts()?
+    } else {
+        client.call(api::List {})?
+    };
+    let selected = self::select(&posts, select, dateformat::today(config.timezone))?;
+
+    let changes = selected
+        .into_iter()
+        .map(|post| {
+            let before = post.publish_date();
+            Change {
+                post,
+                before,
+                after: to(before),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let width = changes.iter().map(|c| c.post.slug.len()).max().unwrap_or(0);
+    for change in &changes {
+        let (slug, before) = (&change.post.slug, show(change.before));
+        if change.before == change.after {
+            println!("{slug:<width$}  {before:<10}  (unchanged)");
+        } else {
+            println!("{slug:<width$}  {before:<10}  -> {}", show(change.after));
+        }
+    }
+
+    let changes = changes
+        .into_iter()
+        .filter(|change| change.before != change.after)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        println!("Nothing to change");
+        return Ok(());
+    }
+
+    if !yes && !opts.dry_run && !confirm(changes.len())? {
+        println!("Nothing was changed");
+        return Ok(());
+    }
+
+    let mut failed = Vec::new();
+    for change in &changes {
+        let slug = &change.post.slug;
+        let result = api::Update::builder()
+            .slug(slug.clone())
+            .published_at(Some(change.after))
+            .build()
+            .run(client, opts, |_| Ok(()));
+        if let Err(error) = result {
+            error!(slug, ?error, "Failed to update");
+            failed.push(slug.as_str());
+        }
+    }
+
+    if opts.dry_run {
+        return Ok(());
+    }
+    let updated = changes.len() - failed.len();
+    println!("Updated {updated} of {} posts", changes.len());
+    ensure!(
+        failed.is_empty(),
+        "{} posts were not updated: {}",
+        failed.len(),
+        failed.join(", ")
+    );
+    Ok(())
+}
+
+/// The posts that match all of the selectors, in the order of `posts`.
+fn select<'a>(posts: &'a [Post], select: &Select, today: NaiveDate) -> Result<Vec<&'a Post>> {
+    for slug in &select.slugs {
+        if !slug.contains('*') && !posts.iter().any(|post| &post.slug == slug) {
+            bail!("There is no post '{slug}'");
+        }
+    }
+    let series = select
+        .series
+        .as_deref()
+        .map(|name| series::slugs(posts, name))
+        .transpose()?;
+    let states = select.drafts || select.scheduled || select.published;
+
+    let selected = posts
+        .iter()
+        .filter(|post| {
+            select.slugs.is_empty()
+                || select
+                    .slugs
+                    .iter()
+                    .any(|pattern| matches(pattern, &post.slug))
+        })
+        .filter(|post| series.as_ref().map_or(true, |s| s.contains(&post.slug)))
+        .filter(|post| {
+            !states
+                || match post.publish_date() {
+                    PublishDate::Draft => select.drafts,
+                    PublishDate::Date(date) if date > today => select.scheduled,
+                    PublishDate::Date(_) => select.published,
+                }
+        })
+        .collect::<Vec<_>>();
+
+    ensure!(!selected.is_empty(), "No post is selected");
+    Ok(selected)
+}
+
+/// Whether `slug` matches `pattern`, in which `*` matches any text.
+fn matches(pattern: &str, slug: &str) -> bool {
+    let mut parts = pattern.split('*').collect::<Vec<_>>();
+    let first = parts.remove(0);
+    let Some(mut rest) = slug.strip_prefix(first) else {
+        return false;
+    };
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+fn show(date: PublishDate) -> String {
+    match date {
+        PublishDate::Date(date) => date.format("%Y-%m-%d").to_string(),
+        PublishDate::Draft => String::from("draft"),
+    }
+}
+
+/// Asks whether to update `count` posts, which needs a terminal.
+fn confirm(count: usize) -> Result<bool> {
+    ensure!(
+        io::stdin().is_terminal(),
+        "Confirm the changes with --yes when not running in a terminal"
+    );
+    eprint!("Update {count} posts? [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    let _ = io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[test]
+fn match_slugs() {
+    assert!(matches("weekly-*", "weekly-notes-3"));
+    assert!(matches("*-notes-*", "weekly-notes-3"));
+    assert!(matches("a*b*a", "aba"));
+    assert!(!matches("a*b*a", "ab"));
+    assert!(!matches("weekly", "weekly-notes-3"));
+}
+
+#[test]
+fn select_by_slug() {
+    let post = |slug: &str, published_at: &str| Post {
+        slug: slug.to_owned(),
+        title: None,
+        body: None,
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let posts = [
+        post("weekly-1", "2015-10-14"),
+        post("weekly-2", "2015-10-28"),
+        post("weekly-3", ""),
+        post("other", ""),
+    ];
+    let today = NaiveDate::from_ymd_opt(2015, 10, 21).unwrap();
+    let by = |slugs: &[&str], drafts, scheduled| Select {
+        slugs: slugs.iter().map(|&s| s.to_owned()).collect(),
+        drafts,
+        scheduled,
+        published: false,
+        series: None,
+    };
+    let slugs = |selected: Vec<&Post>| selected.iter().map(|p| p.slug.clone()).collect::<Vec<_>>();
+
+    let selected = select(&posts, &by(&["weekly-*"], false, false), today).unwrap();
+    assert_eq!(slugs(selected), ["weekly-1", "weekly-2", "weekly-3"]);
+    let selected = select(&posts, &by(&["other", "weekly-1"], false, false), today).unwrap();
+    assert_eq!(slugs(selected), ["weekly-1", "other"]);
+}
+
+#[test]
+fn select_by_state() {
+    let post = |slug: &str, published_at: &str| Post {
+        slug: slug.to_owned(),
+        title: None,
+        body: None,
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let posts = [
+        post("weekly-1", "2015-10-14"),
+        post("weekly-2", "2015-10-28"),
+        post("weekly-3", ""),
+        post("other", ""),
+    ];
+    let today = NaiveDate::from_ymd_opt(2015, 10, 21).unwrap();
+    let by = |slugs: &[&str], drafts, scheduled| Select {
+        slugs: slugs.iter().map(|&s| s.to_owned()).collect(),
+        drafts,
+        scheduled,
+        published: false,
+        series: None,
+    };
+    let slugs = |selected: Vec<&Post>| selected.iter().map(|p| p.slug.clone()).collect::<Vec<_>>();
+
+    let selected = select(&posts, &by(&["weekly-*"], true, true), today).unwrap();
+    assert_eq!(slugs(selected), ["weekly-2", "weekly-3"]);
+    let selected = select(&posts, &by(&[], true, false), today).unwrap();
+    assert_eq!(slugs(selected), ["weekly-3", "other"]);
+}
+
+#[test]
+fn select_nothing() {
+    let posts = [Post {
+        slug: String::from("weekly-1"),
+        title: None,
+        body: None,
+        published_at: Some(String::from("2015-10-14")),
+        url: None,
+    }];
+    let today = NaiveDate::from_ymd_opt(2015, 10, 21).unwrap();
+    let by = |slug: &str| Select {
+        slugs: vec![slug.to_owned()],
+        drafts: false,
+        scheduled: false,
+        published: false,
+        series: None,
+    };
+    assert!(select(&posts, &by("missing"), today).is_err());
+    assert!(select(&posts, &by("x-*"), today).is_err());
+}
@@ -0,0 +1,292 @@
+use std::ops::Range;
+
+use kommandozeile::tracing::warn;
+use pulldown_cmark::{CowStr, Event, LinkType, Parser, Tag};
+
+use crate::{preview, Post};
+
+/// A link in the body of a post that points to a post of the same blog.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    /// The slug of the post that is linked to.
+    pub slug: String,
+    /// The link destination as it appears in the body.
+    pub dest: String,
+    /// Where the destination appears in the body.
+    pub range: Range<usize>,
+    /// The line of the body (starting at 1) that contains the link.
+    pub line: usize,
+    /// Where the slug appears in the destination.
+    slug_range: Range<usize>,
+}
+
+impl Link {
+    /// The destination of this link, but pointing to `slug` instead.
+    pub fn with_slug(&self, slug: &str) -> String {
+        let mut dest = self.dest.clone();
+        dest.replace_range(self.slug_range.clone(), slug);
+        dest
+    }
+}
+
+/// The ways in which posts of a blog can be linked to.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Blog {
+    prefixes: Vec<String>,
+}
+
+impl Blog {
+    /// Derives the addresses of the blog from the URLs of its posts, plus
+    /// any additional (e.g. custom) domains.
+    ///
+    /// Posts can be linked to relative to the blog (`/blog/<slug>/`) or
+    /// absolute, using either `http` or `https`.
+    pub fn new<'a>(posts: &[Post], domains: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut hosts = posts
+            .iter()
+            .filter_map(|post| {
+                let url = post.url.as_deref()?;
+                let (_, rest) = url.split_once("://")?;
+                let (host, _) = rest.split_once('/')?;
+                Some(host.to_owned())
+            })
+            .collect::<Vec<_>>();
+        hosts.extend(domains.into_iter().map(|d| {
+            let d = d.split_once("://").map_or(d, |(_, d)| d);
+            d.trim_end_matches('/').to_owned()
+        }));
+        hosts.sort();
+        hosts.dedup();
+
+        let mut prefixes = hosts
+            .iter()
+            .flat_map(|host| {
+                [
+                    format!("https://{host}/blog/"),
+                    format!("http://{host}/blog/"),
+                    format!("//{host}/blog/"),
+                ]
+            })
+            .collect::<Vec<_>>();
+        prefixes.push(String::from("/blog/"));
+
+        Self { prefixes }
+    }
+
+    /// The range of the slug in `dest`, if `dest` points to a post of this blog.
+    pub fn slug_range(&self, dest: &str) -> Option<Range<usize>> {
+        let prefix = self
+            .prefixes
+            .iter()
+            .find(|p| dest.starts_with(p.as_str()))?;
+        let start = prefix.len();
+        let end = dest[start..]
+            .find(['/', '?', '#'])
+            .map_or(dest.len(), |end| start + end);
+        (end > start).then_some(start..end)
+    }
+}
+
+/// Finds all links in `body` that point to posts of `blog`.
+///
+/// Inline links, autolinks, images, and reference definitions are found.
+/// The same reference definition is only reported once, even if it is used
+/// by multiple links.
+/// Destinations with escapes or entities are skipped with a warning, since
+/// they cannot be rewritten as they appear in the body.
+pub fn find(body: &str, blog: &Blog) -> Vec<Link> {
+    let mut found = Vec::new();
+    let mut parser = Parser::new_ext(body, preview::options()).into_offset_iter();
+    // the open links and images, with the end of their text so far
+    let mut open = Vec::<(LinkType, CowStr<'_>, usize)>::new();
+
+    for (event, range) in parser.by_ref() {
+        match event {
+            Event::Start(Tag::Link(kind, dest, _) | Tag::Image(kind, dest, _)) => {
+                let text_start = if body[range.clone()].starts_with('!') {
+                    range.start + 2
+                } else {
+                    range.start + 1
+                };
+                open.push((kind, dest, text_start));
+                continue;
+            }
+            Event::End(Tag::Link(..) | Tag::Image(..)) => {
+                let Some((kind, dest, text_end)) = open.pop() else {
+                    continue;
+                };
+                // reference links point to their definition, which is handled below
+                let at = match kind {
+                    LinkType::Inline => body[text_end..]
+                        .starts_with("](")
+                        .then(|| destination(body, text_end + 2))
+                        .flatten(),
+                    LinkType::Autolink => Some(range.start + 1..range.end - 1),
+                    _ => None,
+                };
+                if let Some(at) = at {
+                    found.extend(located(body, blog, &dest, at));
+                }
+            }
+            _ => {}
+        }
+        if let Some(parent) = open.last_mut() {
+            parent.2 = parent.2.max(range.end);
+        }
+    }
+
+    for (_, def) in parser.reference_definitions().iter() {
+        let span = &body[def.span.clone()];
+        let label_end = span
+            .char_indices()
+            .find(|&(at, c)| c == ']' && !span[..at].ends_with('\\'))
+            .map(|(at, _)| at);
+        let Some(label_end) = label_end.filter(|&at| span[at + 1..].starts_with(':')) else {
+            continue;
+        };
+        if let Some(at) = destination(body, def.span.start + label_end + 2) {
+            found.extend(located(body, blog, &def.dest, at));
+        }
+    }
+
+    found.sort_by_key(|l| l.range.start);
+    found.dedup_by_key(|l| l.range.start);
+    found
+}
+
+/// The range of the link destination that starts at `at` in `body`, after
+/// optional whitespace, without the angle brackets of `<...>` destinations.
+fn destination(body: &str, at: usize) -> Option<Range<usize>> {
+    let rest = &body[at..];
+    let start = at + (rest.len() - rest.trim_start().len());
+    let rest = &body[start..];
+
+    if let Some(inner) = rest.strip_prefix('<') {
+        let mut escaped = false;
+        let end = inner.find(|c| {
+            let end = !escaped && matches!(c, '>' | '\n');
+            escaped = !escaped && c == '\\';
+            end
+        })?;
+        return inner[end..]
+            .starts_with('>')
+            .then_some(start + 1..start + 1 + end);
+    }
+
+    let (mut depth, mut escaped) = (0_usize, false);
+    let end = rest
+        .find(|c: char| {
+            if escaped {
+                escaped = false;
+                return false;
+            }
+            match c {
+                '\\' => escaped = true,
+                '(' => depth += 1,
+                ')' if depth == 0 => return true,
+                ')' => depth -= 1,
+                c if c.is_whitespace() => return true,
+                _ => {}
+            }
+            false
+        })
+        .unwrap_or(rest.len());
+    Some(start..start + end)
+}
+
+/// The link to `dest` at `range` in `body`, if it points to a post of `blog`.
+fn located(body: &str, blog: &Blog, dest: &str, range: Range<usize>) -> Option<Link> {
+    let slug_range = blog.slug_range(dest)?;
+    if body[range.clone()] != *dest {
+        warn!(
+            line = line_of(body, range.start),
+            dest, "Skipped a link with escapes or entities in its destination"
+        );
+        return None;
+    }
+    Some(Link {
+        slug: dest[slug_range.clone()].to_owned(),
+        dest: dest.to_owned(),
+        range: range.clone(),
+        line: line_of(body, range.start),
+        slug_range,
+    })
+}
+
+/// The line (starting at 1) of the byte `offset` in `text`.
+pub fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count() + 1
+}
+
+/// Replaces the destinations of the given links in `body`.
+pub fn rewrite<'a>(body: &str, rewrites: impl IntoIterator<Item = (&'a Link, String)>) -> String {
+    let mut rewrites = rewrites.into_iter().collect::<Vec<_>>();
+    rewrites.sort_by_key(|(link, _)| std::cmp::Reverse(link.range.start));
+
+    let mut body = body.to_owned();
+    for (link, dest) in rewrites {
+        body.replace_range(link.range.clone(), &dest);
+    }
+    body
+}
+
+#[test]
+fn find_internal_links() {
+    let post = Post {
+        slug: String::from("other"),
+        title: None,
+        body: None,
+        published_at: None,
+        url: Some(String::from("https://me.mataroa.blog/blog/other/")),
+    };
+    let blog = Blog::new(&[post], ["example.com"]);
+
+    let body = concat!(
+        "See [one](/blog/one/) and [two](https://me.mataroa.blog/blog/two/#top).\n",
+        "Also [three][3], <https://example.com/blog/four/>, and [ext](https://example.org/blog/x/).\n",
+        "\n",
+        "[3]: http://me.mataroa.blog/blog/three/\n",
+    );
+
+    let links = find(body, &blog);
+    let slugs = links.iter().map(|l| l.slug.as_str()).collect::<Vec<_>>();
+    assert_eq!(slugs, ["one", "two", "four", "three"]);
+    assert_eq!(links[2].line, 2);
+    assert_eq!(links[3].line, 4);
+    assert_eq!(
+        links[1].with_slug("deux"),
+        "https://me.mataroa.blog/blog/deux/#top"
+    );
+
+    let body = rewrite(body, [(&links[0], links[0].with_slug("uno"))]);
+    assert!(body.starts_with("See [one](/blog/uno/) and"));
+}
+
+#[test]
+fn find_links_with_titles() {
+    let blog = Blog::new(&[], []);
+    let body = concat!(
+        "A [/blog/one/](/blog/one/ \"See /blog/one/\") and ",
+        "![/blog/two/](</blog/two/> '/blog/two/').\n",
+        "[![img](/blog/img/)](/blog/three/) and [esc](/blog/fo\\_ur/).\n",
+        "\n",
+        "[def]: </blog/five/> \"/blog/five/\"\n",
+    );
+
+    let links = find(body, &blog);
+    let slugs = links.iter().map(|l| l.slug.as_str()).collect::<Vec<_>>();
+    assert_eq!(slugs, ["one", "two", "img", "three", "five"]);
+    for link in &links {
+        assert_eq!(body[link.range.clone()], link.dest);
+    }
+    assert_eq!(links[0].range.start, body.find("](").unwrap() + 2);
+    assert_eq!(
+        rewrite(body, [(&links[1], links[1].with_slug("deux"))])
+            .lines()
+            .next()
+            .unwrap()
+            .rsplit(" and ")
+            .next(),
+        Some("![/blog/two/](</blog/deux/> '/blog/two/').")
+    );
+}
@@ -74,6 +74,27 @@
 //! This is the least secure option, as the API key will be visible in the
 //! process list of the shell.
 //!
+//! ## Configuration
+//!
+//! Some features can be configured in a TOML file, which is read from
+//! `<config dir>/mataroa/config.toml` (e.g. `~/.config/mataroa/config.toml` on Linux).
+//! A different file can be used with the `--config` flag or the `MATAROA_CONFIG`
+//! environment variable.
+//! The file is optional, unless it is given explicitly.
+//!
+//! Settings can be grouped into profiles, which are selected with the `--profile` flag
+//! or the `MATAROA_PROFILE` environment variable.
+//! The settings of the selected profile replace the top-level settings:
+//!
+//! ```toml
+//! [transforms]
+//! typography = true
+//!
+//! [profiles.work.transforms]
+//! typography = false
+//! toc = true
+//! ```
+//!
 //!
 //! ## Commands
 //!
@@ -85,6 +106,25 @@
 //! - `get`: Get a post
 //! - `update` or `edit`: Update a post
 //! - `delete` or `rm`: Delete a post
+//! - `preview`: Render a post as a local HTML page
+//! - `watch`: Watch post files and push every saved change
+//! - `slug`: Predict the slug of a new post
+//! - `rename`: Change the slug of a post and rewrite all links to it
+//! - `check-links`: Check the links between posts
+//! - `graph`: Export the links between posts as a graph
+//! - `fmt`: Format post files in place
+//! - `note`: Post a short note
+//! - `schedule`: Show the editorial calendar and schedule drafts
+//! - `calendar`: Export the publishing plan as a calendar
+//! - `scheduler`: Run and manage the local publish scheduler
+//! - `date`: Show the date that a date expression resolves to
+//! - `series`: Manage series of posts
+//! - `split`: Split a long post file into a series of part files
+//! - `index`: Update the index post with a list of all published posts
+//! - `expire`: Apply the retention policies to the posts
+//! - `publish`: Publish posts today
+//! - `unpublish`: Turn posts back into drafts
+//! - `reschedule`: Move posts to another date
 //!
 //! ### Command output
 //!
@@ -109,6 +149,20 @@
 //! Using the last option will send the empty string `""` as value, which will cause the post
 //! to be a draft. Often the `published_at` field is optional, so you can omit it entirely.
 //!
+//! Besides the descriptions that [human-date-parser](https://docs.rs/human-date-parser)
+//! understands, there are expressions that are relative to today, like `+3d`, `-2w`,
+//! `+1m`, `+1y`, `end of month`, `start of next week`, or `end of last year`.
+//! On the command line, e.g. with `--published-at`, and as the `published_at` of a post
+//! file, a date can also be relative to the date of another post, which is looked up
+//! via the API when the post is created or updated:
+//! `same as <slug>`, `2 weeks after <slug>`, `a day before <slug>`, or `3d after <slug>`.
+//!
+//! The `date` command shows which date an expression resolves to, without using it:
+//!
+//! ```sh
+//! roa date 2 weeks after my-previous-post
+//! ```
+//!
 //! ### POST <> File mapping
 //!
 //! Several commands accept a file as input.
@@ -118,7 +172,9 @@
 //! processing via a frontmatter.
 //!
 //! The frontmatter is a YAML or JSON code block at the beginning of the file.
-//! It can contain the fields `title`, `slug`, or `published_at`.
+//! It can contain the fields `title`, `slug`, `published_at`, `url`, `vars`, `transforms`, `series`, or `expires`
+//! (the `url` is only informational and is never sent to mataroa,
+//! the `vars` are for [templates](#templates), the `transforms` for [transforms](#transforms), the `series` for [series](#series), and `expires` for [expiry](#expiry)).
 //! Depending on the command, some of these fields are required.
 //! Before the fromtmatter, there can be a leading h1, which will be used as the title for the blog post.
 //! An explicit title in the frontmatter overrides the h1 title.
@@ -154,6 +210,127 @@
 //! Commands that allow their values to be specified via a flag (e.g. `--title`) will use
 //! the value from the flag if it is provided, even if the file contains a value.
 //!
+//! #### Wiki-links
+//!
+//! The body can use wiki-links like Obsidian does: `[[Other Note]]`, `[[other-slug|label]]`,
+//! `[[Other Note#Some heading]]`, or `[[#A heading in this post]]`.
+//! Before `create` and `update` (and `watch`) send the body, the wiki-links are replaced
+//! with markdown links to the posts they point to.
+//! A target is first matched against the names of the markdown files next to the post file,
+//! which must have been created already so that they have a slug in their frontmatter,
+//! and then against the slugs and titles of the posts on mataroa (ignoring case).
+//! Only the files directly next to the post file are considered, and none for a post
+//! read from stdin.
+//! If any wiki-link cannot be resolved, nothing is sent.
+//! With `--dry-run`, the posts on mataroa are not looked up, and wiki-links to them are kept.
+//!
+//! Embeds like `![[Other Note]]` become regular links, since mataroa cannot embed posts.
+//! Embeds of anything else, like `![[image.png]]`, become images.
+//!
+//! When writing a post to a file with `get --output`, the `--wiki-links` flag translates
+//! links to other posts back into wiki-links.
+//!
+//! #### Includes
+//!
+//! The body can include the content of other files, like mdBook does:
+//!
+//! ```markdown
+//!     {{#include snippets/footer.md}}
+//!
+//!     ```rust
+//!     {{#include ../src/main.rs:10:20}}
+//!     ```
+//! ```
+//!
+//! A file can be included as a whole, by line range (`file:10:20`, `file:10:`, `file::20`),
+//! as a single line (`file:10`), or by a named anchor (`file:name`), which includes the lines
+//! between `ANCHOR: name` and `ANCHOR_END: name` (usually in comments).
+//! Paths are relative to the file that contains the include, and included markdown files
+//! can include other files themselves, as long as that doesn't lead to a cycle.
+//! Includes are expanded whenever a post file is read, so `--dry-run` shows the expanded
+//! body, and `preview` renders it.
+//! An include can be escaped as `\{{#include ...}}`.
+//!
+//! #### Templates
+//!
+//! After the includes, template expressions in double braces are expanded:
+//!
+//! - `{{ title }}`, `{{ slug }}`, `{{ published_at }}`, `{{ url }}`, and `{{ today }}`
+//! - `{{ env.NAME }}` for the environment variable `NAME`
+//! - any value from the `vars` map in the frontmatter, e.g. `{{ topic }}` for `vars: { topic: cats }`
+//!
+//! Values can be passed through filters: `{{ published_at | date("%B %Y") }}` formats a date,
+//! `default("text")` replaces an undefined value, and `upper`, `lower`, and `trim` do what they say.
+//! Using an undefined value without a `default` is an error, and nothing is sent.
+//!
+//! Shortcodes are markdown snippets with parameters, defined in the configuration:
+//!
+//! ```toml
+//! [shortcodes.youtube]
+//! params = ["id", "title"]
+//! body = """
+//! <iframe src="https://www.youtube.com/embed/{{ id }}" title="{{ title | default("Video") }}"></iframe>
+//! """
+//! ```
+//!
+//! They are used like `{{ youtube("dQw4w9WgXcQ") }}` or `{{ youtube(id="dQw4w9WgXcQ", title=title) }}`,
+//! with strings or variables as arguments.
+//! An expression can be escaped as `\{{ ... }}`.
+//! Expressions in code spans and code blocks are kept as they are.
+//!
+//! #### Transforms
+//!
+//! After the templates, these transforms can be applied to the body:
+//!
+//! - `shift_headings`: Shift all headings so that the highest one is an h2,
+//!   since the h1 is the title of the post.
+//! - `toc`: Generate a table of contents at the `<!-- toc -->` marker.
+//!   The table ends with a `<!-- /toc -->` marker, and is replaced when it is generated again.
+//! - `footnotes`: Number the footnotes in the order they are referenced and collect
+//!   their definitions at the end of the body.
+//! - `typography`: Use typographic quotes, dashes, and ellipses (outside of code).
+//!
+//! They are all off by default and can be enabled in the `[transforms]` section of the
+//! [configuration](#configuration), per profile, or per post in the frontmatter:
+//!
+//! ```yml
+//! transforms:
+//!   toc: true
+//!   typography: false
+//! ```
+//!
+//! The `fmt` command applies the transforms to post files in place, so that the file
+//! contains the same body that is published.
+//! More transforms can be applied with `--transform`.
+//!
+//! ```sh
+//! roa fmt --transform shift-headings --transform footnotes post.md
+//! ```
+//!
+//! It also writes the files in a canonical form:
+//!
+//! - The frontmatter keys are ordered (`title`, `slug`, `published_at`, `url`, then the rest)
+//!   and all strings are double quoted.
+//! - Headings use `#`, lists use `-` and `1.`, emphasis uses `*` and `**`,
+//!   and code blocks are fenced.
+//! - There is exactly one blank line between blocks and no trailing whitespace.
+//! - Reference definitions are gathered at the end of the file.
+//! - Template expressions (`{{ ... }}`), includes, and wiki-links are kept as they are.
+//!
+//! Paragraphs keep their line breaks, unless `--wrap` is given:
+//! `--wrap 80` fills lines up to 80 characters, `--wrap sentence` puts every
+//! sentence on its own line.
+//! The default can be set in the config:
+//!
+//! ```toml
+//! [fmt]
+//! wrap = "sentence"
+//! ```
+//!
+//! With `--check`, no file is written; the command lists the files that are not
+//! formatted and fails if there are any, e.g. in CI.
+//! Since it only works on local files, `fmt` needs no API key.
+//!
 //! #### File usage per command
 //!
 //! ##### `create`
@@ -164,6 +341,39 @@
 //! The `published_at` value can be set and is optional.
 //!
 //! The `slug` value will be ignored, as this one will be generated by the API.
+//! After the post is created, the slug, the `published_at` date, and the URL that mataroa
+//! returned are written back into the frontmatter of the file, so that the file can be
+//! used with `update` right away (this also applies to `create` commands in a `batch`).
+//! The rest of the file is left untouched.
+//! Use `--no-write-back` to leave the file as it is.
+//!
+//! With `--template <name>`, a new post file is written from the template
+//! `templates/<name>.md` in the config directory instead.
+//! Template expressions in it are expanded with the variables `today`, `date` (the
+//! `published_at` date, or today for drafts), and `count`, which is one more than the highest number in the
+//! titles of existing posts that match the title of the template.
+//! All other expressions are kept and expanded when the post is published.
+//! A date expression as `published_at`, like `next friday`, is replaced by the date.
+//!
+//! ````md
+//! # Weekly Notes #{{ count }}
+//!
+//! ```yml
+//! published_at: next friday
+//! ```
+//!
+//! What happened in the week before {{ date | date("%B %-d") }}.
+//! ````
+//!
+//! The file is named after the slug of the title, unless a file name is given.
+//! With `--edit`, the file is opened in `$VISUAL` or `$EDITOR`, and the post is created
+//! when the editor exits successfully.
+//! With `--dry-run`, the file is printed instead of written, and `count` is kept
+//! because the posts are not looked up.
+//!
+//! ```sh
+//! roa new --template weekly-notes --edit
+//! ```
 //!
 //! ##### `get`
 //!
@@ -205,11 +415,387 @@
 //! # delete the post
 //! rm post
 //! ```
-use std::{fmt::Display, fs::File, io::Write as _};
+//!
+//! ### Preview
+//!
+//! The `preview` command renders a post into a standalone HTML page, so that it can be
+//! looked at in a browser before it is published.
+//! The post can be given as a file (using the same format as `create` and `update`) or
+//! as the slug of an existing post.
+//! The body is rendered with the same markdown extensions that mataroa uses
+//! (tables, footnotes, strikethrough, and task lists), with the title and the
+//! publish date as a header.
+//!
+//! ```sh
+//! roa preview --output post.html post.md
+//! ```
+//!
+//! With `--serve`, the command starts a local server instead (listening on `127.0.0.1:4000`,
+//! which can be changed with `--addr`).
+//! The server takes a post file or a directory of post files and renders them on every request.
+//! The files are watched for changes and any open page reloads automatically when a file is saved.
+//! Errors, for example in the frontmatter, are shown in the page instead of stopping the server.
+//!
+//! ```sh
+//! roa preview --serve posts/
+//! ```
+//!
+//! ### Watch
+//!
+//! The `watch` command watches a post file or a directory of post files and pushes
+//! every saved change to mataroa, e.g. for live-blogging an event.
+//! Saves are debounced (by 500ms, which can be changed with `--debounce`) and a post
+//! is only pushed if its parsed content actually changed.
+//! Files with a slug update the existing post, files without a slug create a new post
+//! and the slug that was assigned by mataroa is written back into their frontmatter.
+//! Pushes that fail because of network or server errors are retried later.
+//! Before a failed create is retried, the post is looked up by its title, since the
+//! failed attempt might have created it anyway.
+//! With `--dry-run`, the requests are printed instead; `--json` is not supported.
+//!
+//! ```sh
+//! roa watch posts/
+//! ```
+//!
+//! ### Slugs
+//!
+//! mataroa generates the slug of a new post from its title, so the final slug is only known
+//! after the post was created.
+//! The `slug` command predicts the slug for a title, using the same rules as mataroa,
+//! and warns if that slug is already used by another post (in which case mataroa
+//! adds a random suffix to it).
+//!
+//! ```sh
+//! roa slug "My first post"
+//! ```
+//!
+//! The same check runs before `create` and before `update` changes the slug of a post,
+//! where the new slug is checked exactly as it is given.
+//! Pass `--abort-on-collision` to fail instead of only warning.
+//!
+//! Changing the slug of a post breaks all links to it.
+//! The `rename` command changes the slug and then rewrites the links to the old slug
+//! in the bodies of all posts.
+//! Links relative to the blog (`/blog/<slug>/`) and absolute links to the domain of the
+//! blog are found; additional domains (e.g. a custom domain) can be passed with `--domain`.
+//! The planned rewrites are printed before they are applied (use `--dry-run` to only see them).
+//! If any of the updates fails, all previous changes are reverted.
+//!
+//! ```sh
+//! roa rename my-frist-post my-first-post
+//! ```
+//!
+//! ### Checking links
+//!
+//! The `check-links` command finds all links between the posts of the blog and reports
+//! - broken links, that point to a post that doesn't exist
+//! - links to drafts
+//! - links to posts that are scheduled to be published after the post with the link
+//!   (or after today, if the post with the link is a draft)
+//!
+//! Every problem is reported with the slug of the post and the line of the link.
+//! The command fails if there were any problems, so it can be used in scripts.
+//! Like for `rename`, additional domains of the blog can be passed with `--domain`.
+//!
+//! ### Graph
+//!
+//! The `graph` command exports the links between the posts as a graph,
+//! either as `dot` (the default), `mermaid`, or `json`.
+//!
+//! ```sh
+//! roa graph --format mermaid --output links.mmd
+//! ```
+//!
+//! With `--orphans`, it lists the slugs of all posts that no other post links to.
+//!
+//! With `--backlinks`, it adds a "Linked from" section to the end of every post
+//! that other posts link to, and updates it when the links change.
+//! The section is wrapped in `<!-- roa:backlinks -->` comments; everything between
+//! them is replaced on the next run, and links in it are not counted as links.
+//!
+//! ### Notes
+//!
+//! The `note` command posts a short note, which is published today.
+//! The text is given as arguments, or read from stdin if there are none.
+//!
+//! ```sh
+//! roa note "Just released version 2. It has notes!"
+//! ```
+//!
+//! The title is generated from the first sentence of the text, and the slug from
+//! the current date and time (e.g. `2024-05-17-1430`).
+//! Both can be changed in the `[note]` section of the [configuration](#configuration),
+//! with template expressions that can use `text`, `first_line`, `first_sentence`,
+//! `date`, and `time`:
+//!
+//! ```toml
+//! [note]
+//! title = "Note from {{ date }} {{ time }}"
+//! slug = "note-{{ date }}-{{ time }}"
+//! daily_title = "Notes from {{ date }}"
+//! ```
+//!
+//! With `--append-to-daily`, the note is appended to today's daily post instead, as a
+//! new paragraph that starts with the current time.
+//! The daily post is found by the slug of `daily_title`, and created if it doesn't exist yet.
+//! With `--dry-run`, the daily post is not looked up and only the new entry is shown.
+//!
+//! ### Editorial calendar
+//!
+//! The `schedule` command shows the published and scheduled posts of the last
+//! and next four weeks (change it with `--weeks`), together with the free slots of
+//! the publishing cadence.
+//! The cadence is a list of weekdays in the `[schedule]` section of the [configuration](#configuration):
+//!
+//! ```toml
+//! [schedule]
+//! cadence = ["tue", "fri"]
+//! ```
+//!
+//! A slot is free if no post is published or scheduled on that day.
+//! With `--fill`, the given drafts are scheduled into the next free slots after
+//! today, in the given order:
+//!
+//! ```sh
+//! roa schedule --fill first-draft second-draft
+//! ```
+//!
+//! The plan is printed before the posts are updated, and with `--dry-run`
+//! nothing is changed.
+//!
+//! ### Calendar export
+//!
+//! The `calendar` command lists every published and scheduled post on its publish date.
+//! With `--ics`, it writes an iCalendar file instead, with one all-day event per post,
+//! titled and linked like the post.
+//! Scheduled posts are marked as tentative and their titles start with "Scheduled:".
+//! Drafts are left out, since they don't have a date.
+//!
+//! ```sh
+//! roa calendar --ics --output ~/calendars/blog.ics
+//! ```
+//!
+//! Every event has a UID that is derived from the slug of the post, so a calendar
+//! app that imports or subscribes to the file updates the events on the next export
+//! instead of adding them again.
+//!
+//! ### Publishing at a time of day
+//!
+//! mataroa only knows the date a post is published on, so to publish a draft at a
+//! specific time, `schedule --at` records a job in a local queue instead:
+//!
+//! ```sh
+//! roa schedule --at "2026-11-02 09:00 Europe/Berlin" my-draft
+//! ```
+//!
+//! Without a time zone, the [time zone of the blog](#time-zone) is used.
+//! Scheduling the same draft again replaces its job.
+//!
+//! The jobs are run by the scheduler, which publishes a draft by setting its
+//! publish date to today once the time of its job has come:
+//!
+//! ```sh
+//! roa scheduler run
+//! ```
+//!
+//! It runs until it is interrupted, runs jobs that were missed while it was not
+//! running right away, and retries failed jobs after a minute.
+//! A job that mataroa rejects, e.g. because the draft was deleted, is dropped instead.
+//! With `--due`, only the jobs that are due are run and the command exits, so it
+//! can be called from cron or a systemd timer instead.
+//!
+//! `roa scheduler list` shows the queued jobs, and `roa scheduler cancel <id or slug>`
+//! removes one.
+//! The queue is kept in `<data dir>/mataroa/jobs.json` (e.g. `~/.local/share/mataroa/jobs.json` on Linux),
+//! or in the file that is set in the [configuration](#configuration):
+//!
+//! ```toml
+//! [scheduler]
+//! queue = "/home/me/blog/jobs.json"
+//! ```
+//!
+//! ### Time zone
+//!
+//! mataroa only stores the date a post is published on, which depends on the time zone.
+//! By default, the local time zone is used to decide what today is, which posts are
+//! scheduled instead of published, and what relative dates like `tomorrow` or
+//! `next friday` mean.
+//! When the blog is written from different time zones, the time zone of the blog can
+//! be set in the [configuration](#configuration), or with `--tz` (or `MATAROA_TZ`):
+//!
+//! ```toml
+//! timezone = "Europe/Berlin"
+//! ```
+//!
+//! All dates are then relative to the current date in that time zone.
+//! When a date means a different day in the time zone of the blog than locally,
+//! e.g. `tomorrow` late in the evening, a warning is printed.
+//!
+//! ### Series
+//!
+//! A post belongs to a series if its frontmatter has a `series` with the name of the
+//! series and the position of the post in it:
+//!
+//! ```yaml
+//! series:
+//!   name: "Writing a CLI in Rust"
+//!   order: 2
+//! ```
+//!
+//! Since mataroa has no frontmatter, this is recorded in the body of the post,
+//! in a `<!-- roa:series {...} -->` comment.
+//! The `series` command works with the series of all posts:
+//!
+//! - `series list [<name>]` lists all series, or the parts of one series in order.
+//! - `series reorder <name> <slug>...` moves the given parts to the front, in the given order,
+//!   and numbers all parts from 1.
+//! - `series schedule <name> --every 1w [--start <date>]` schedules the parts that are still drafts,
+//!   one interval apart.
+//!   The interval is given like a [relative date](#date-format) (`3d`, `2w`, `1m`).
+//!   The first part is scheduled at `--start`, or one interval after the last dated part (or today).
+//! - `series nav [<name>]` refreshes the header and the navigation of the parts of one or all series.
+//!
+//! Reordering and `nav` add a "Part N of M" header to the start of every part and
+//! links to the previous and next part to the end.
+//! Both are wrapped in `<!-- roa:series-header -->` and `<!-- roa:series-nav -->` comments,
+//! and everything between them is replaced on the next run.
+//! Only parts whose body changes are updated, and with `--dry-run` nothing is changed:
+//!
+//! ```sh
+//! roa --dry-run series nav "Writing a CLI in Rust"
+//! ```
+//!
+//! These commands only change the posts on mataroa, not the post files.
+//! When a part is updated from its post file (with `update` or `watch`), the order,
+//! the header, and the navigation of the part on mataroa are kept, so the `order` in
+//! the frontmatter only matters when the part is created.
+//! If the frontmatter moves the part to another series, nothing is kept; run
+//! `series nav` afterwards to refresh the navigation of both series.
+//!
+//! ### Splitting posts
+//!
+//! The `split` command splits a long post file into a [series](#series) of part files.
+//! The body is split before every heading of the level given with `--at` (`h2` by default)
+//! or higher, and the text before the first of them stays with the first part:
+//!
+//! ```sh
+//! roa split long-post.md --at h2 --every 1w --start "next monday"
+//! ```
+//!
+//! The parts are written next to the file as `long-post-part-1.md`, `long-post-part-2.md`, and so on.
+//! Each part has the frontmatter of the file, with the title "<title> (Part N)",
+//! the slug that mataroa derives from that title, and the series that the parts form.
+//! Reference and footnote definitions are copied into every part that uses them.
+//! The header and the links to the previous and next part are added the same way as by `series nav`,
+//! which can be run again once the parts are published to refresh them.
+//!
+//! The parts are drafts, unless `--every` is given, in which case they are scheduled
+//! one interval apart, starting at `--start`, the date of the file, or one interval after today.
+//! Existing files are never overwritten, and with `--dry-run` no files are written.
+//! The parts can then be created in order:
+//!
+//! ```sh
+//! for part in long-post-part-*.md; do roa create "$part"; done
+//! ```
+//!
+//! ### Index post
+//!
+//! The `index` command keeps a post with a list of all published posts up to date,
+//! e.g. an "All posts" page with the slug `archive`:
+//!
+//! ```sh
+//! roa index --slug archive
+//! ```
+//!
+//! The posts are listed newest first, under a heading for every month (or every year with `--group-by year`).
+//! Drafts, scheduled posts, and the index post itself are left out.
+//! The list is written between `<!-- roa:index -->` comments in the body of the index post;
+//! the rest of the body is kept, and the list is added to the end if there are no comments yet.
+//! The post is only updated if the list has changed, so the command can run from cron
+//! or after every `create`.
+//!
+//! The headings and the posts are rendered with [templates](#templates), which can be changed
+//! in the `[index]` section of the [configuration](#configuration).
+//! A heading can use `year`, `month`, and `date` (the first day of the month or year),
+//! and a post can use `title`, `slug`, `url`, and `published_at`:
+//!
+//! ```toml
+//! [index]
+//! slug = "archive"
+//! group_by = "year"
+//! heading = "## Posts from {{ year }}"
+//! item = "- [{{ title }}]({{ url }}) ({{ published_at | date(\"%b %d\") }})"
+//! ```
+//!
+//! ### Expiry
+//!
+//! A post can have an `expires` date in its frontmatter, e.g. for a time-limited announcement:
+//!
+//! ```yaml
+//! expires: "2024-06-30"
+//! ```
+//!
+//! It must be a date like this, since a relative date like `+7d` would move on every update.
+//! Like the [series](#series), it is recorded in a `<!-- roa:expires {...} -->` comment in the body.
+//! The `expire` command applies the retention policies of the `[expire]` section of the
+//! [configuration](#configuration) to all posts.
+//! Policies that are not set are off, and a profile can set its own:
+//!
+//! ```toml
+//! [expire]
+//! # revert posts to drafts on their expires date, or "delete" them
+//! expired = "draft"
+//! # delete drafts whose title and body haven't changed for 90 days
+//! stale_drafts = 90
+//!
+//! [profiles.work.expire]
+//! expired = "delete"
+//! ```
+//!
+//! Since mataroa doesn't tell when a post was last changed, `expire` records since when each draft
+//! is unchanged in a local file (`<data dir>/mataroa/drafts.json`, or the `state` setting).
+//! A draft counts as changed on the first run that sees it, so `stale_drafts` only deletes drafts
+//! that `expire` has seen unchanged for that many days.
+//!
+//! By default, the changes are only shown; with `--apply`, they are made:
+//!
+//! ```sh
+//! roa expire
+//! roa expire --apply
+//! ```
+//!
+//! Before a post is deleted, it is backed up as a post file to `<data dir>/mataroa/backups`
+//! (or the `backups` setting), from which it can be created again.
+//! With `--dry-run`, neither the drafts nor the backups are written.
+//!
+//! ### Publishing and rescheduling in bulk
+//!
+//! The `publish`, `unpublish`, and `reschedule` commands change the date of many posts at once:
+//!
+//! - `publish` publishes drafts and scheduled posts today; posts that are already published keep their date.
+//! - `unpublish` turns posts back into drafts.
+//! - `reschedule --to <date>` moves posts to a date, which can be any [date expression](#date-format).
+//!
+//! The posts are selected by their slugs, which can use `*` as a wildcard,
+//! and by `--drafts`, `--scheduled`, `--published`, or `--series <name>`.
+//! A post is selected if it matches all of the given selectors:
+//!
+//! ```sh
+//! roa publish --drafts "weekly-*"
+//! roa unpublish --series "Writing a CLI in Rust"
+//! roa reschedule --scheduled --to "next monday"
+//! ```
+//!
+//! The date of every selected post is shown before and after the change, and the changes are
+//! made one after the other once they are confirmed (or right away with `--yes`).
+//! A post that fails to update doesn't stop the others; at the end, a summary shows how many
+//! posts were updated and which failed.
+use std::{collections::BTreeMap, fmt::Display, fs::File, io::Write as _, path::Path};
 
+use chrono_tz::Tz;
 use clap::Parser as _;
 use kommandozeile::{
-    color_eyre::eyre::{bail, OptionExt as _},
+    color_eyre::eyre::{bail, ensure, OptionExt as _, WrapErr as _},
     tracing::warn,
     InputFile, OutputFile, Result,
 };
@@ -220,15 +806,50 @@ use serde::Deserialize;
 use crate::{
     api::{Action, Opts},
     args::Command,
+    config::Config,
     dateformat::PublishDate,
+    links::Blog,
+    transform::Transforms,
 };
 
 mod api;
 mod args;
+mod blocks;
+mod calendar;
+mod check;
+mod cmark;
+mod config;
 mod dateformat;
+mod expire;
+mod fmt;
+mod frontmatter;
+mod graph;
+mod include;
+mod index;
+mod lifecycle;
+mod links;
+mod note;
+mod preview;
+mod rename;
+mod scaffold;
+mod schedule;
+mod scheduler;
+mod series;
+mod serve;
+mod slug;
+mod split;
+mod template;
+mod transform;
+mod watch;
+mod watcher;
+mod wikilinks;
 
 fn main() -> Result<()> {
     let mut args = args::Args::init()?;
+    if let Command::Fmt(cmd) = &args.cmd {
+        // formatting only touches local files, so it needs no API key
+        return fmt::run(&config(&args)?, cmd);
+    }
     let client = api::Client::new(args.api_key()?);
     run(&client, args)
 }
@@ -238,18 +859,46 @@ fn run(client: &api::Client, args: args::Args) -> Result<()> {
         .dry_run(args.dry_run)
         .print_json(args.json)
         .build();
+    let config = config(&args)?;
+
+    cmd(client, opts, &config, args.cmd)
+}
 
-    cmd(client, opts, args.cmd)
+/// Loads the config, with the time zone from the command line, if any.
+fn config(args: &args::Args) -> Result<Config> {
+    let mut config = Config::load(args.config.as_deref(), args.profile.as_deref())?;
+    config.timezone = args.tz.or(config.timezone);
+    Ok(config)
 }
 
-fn cmd(client: &api::Client, opts: Opts, cmd: Command) -> Result<()> {
+fn cmd(client: &api::Client, opts: Opts, config: &Config, cmd: Command) -> Result<()> {
     match cmd {
         Command::List(cmd) => list(client, opts, cmd),
-        Command::Create(cmd) => create(client, opts, cmd),
+        Command::Create(cmd) if cmd.template.is_some() => scaffold::run(client, opts, config, &cmd),
+        Command::Create(cmd) => create(client, opts, config, cmd),
         Command::Get(cmd) => get(client, opts, cmd),
-        Command::Update(cmd) => update(client, opts, cmd),
+        Command::Update(cmd) => update(client, opts, config, cmd),
         Command::Delete(cmd) => delete(client, opts, cmd),
         Command::Batch(cmd) => batch(client, &cmd),
+        Command::Preview(cmd) => preview(client, opts, config, cmd),
+        Command::Watch(cmd) => watch::run(client, opts, config, &cmd),
+        Command::Slug(cmd) => slug(client, opts, &cmd),
+        Command::Rename(cmd) => rename::run(client, opts, &cmd),
+        Command::CheckLinks(cmd) => check::run(client, config, &cmd),
+        Command::Graph(cmd) => graph::run(client, opts, cmd),
+        Command::Fmt(cmd) => fmt::run(config, &cmd),
+        Command::Note(cmd) => note::run(client, opts, config, &cmd),
+        Command::Schedule(cmd) => schedule::run(client, opts, config, &cmd),
+        Command::Calendar(cmd) => calendar::run(client, config, cmd),
+        Command::Scheduler(cmd) => scheduler::run(client, opts, config, &cmd),
+        Command::Date(cmd) => date(client, config, &cmd),
+        Command::Series(cmd) => series::run(client, opts, config, &cmd),
+        Command::Split(cmd) => split::run(client, opts, config, &cmd),
+        Command::Index(cmd) => index::run(client, opts, config, &cmd),
+        Command::Expire(cmd) => expire::run(client, opts, config, &cmd),
+        Command::Publish(cmd) => lifecycle::publish(client, opts, config, &cmd),
+        Command::Unpublish(cmd) => lifecycle::unpublish(client, opts, config, &cmd),
+        Command::Reschedule(cmd) => lifecycle::reschedule(client, opts, config, &cmd),
     }
 }
 
@@ -274,14 +923,43 @@ fn list(client: &api::Client, opts: Opts, cmd: args::List) -> Result<()> {
     })
 }
 
-fn create(client: &api::Client, opts: Opts, cmd: args::Create) -> Result<()> {
-    let post = PostInput::from(cmd.title, None, cmd.published_at, Some(cmd.body))?;
+fn create(client: &api::Client, opts: Opts, config: &Config, cmd: args::Create) -> Result<()> {
+    let body = cmd
+        .body
+        .ok_or_eyre("The post file is required to create a post")?;
+    let write_back = match &body {
+        InputFile::File(path) if !cmd.no_write_back => Some(path.clone()),
+        _ => None,
+    };
+
+    let published_at = cmd
+        .published_at
+        .as_deref()
+        .map(|date| parse_date(client, config, date))
+        .transpose()?;
+    let mut post = PostInput::from(
+        cmd.title,
+        None,
+        published_at,
+        Some(body.clone()),
+        config.timezone,
+    )?;
+    resolve_date(client, config, &mut post)?;
+    post.render(config)?;
+    wikilinks::resolve(client, opts, &mut post, Some(&body))?;
     if post.slug.is_some() {
-        warn!(concat!(
-            "The slug is ignored when creating a new post. ",
-            "If the created slug differed from the one provided, ",
-            "future updates of the post will change that slug."
-        ));
+        if write_back.is_some() {
+            warn!(concat!(
+                "The slug is ignored when creating a new post. ",
+                "It will be replaced by the slug that mataroa assigns to the post."
+            ));
+        } else {
+            warn!(concat!(
+                "The slug is ignored when creating a new post. ",
+                "If the created slug differed from the one provided, ",
+                "future updates of the post will change that slug."
+            ));
+        }
     }
 
     let title = post.title.ok_or_eyre(concat!(
@@ -291,12 +969,17 @@ fn create(client: &api::Client, opts: Opts, cmd: args::Create) -> Result<()> {
         "or as a leading h1 before the post frontmatter"
     ))?;
 
+    slug::preflight(client, opts, &title, cmd.abort_on_collision)?;
+
     api::Create::builder()
         .title(title)
         .body(post.body)
         .published_at(post.published_at)
         .build()
         .run(client, opts, |post| {
+            if let Some(path) = write_back {
+                frontmatter::write_back(&path, &post)?;
+            }
             print_post(post, true, false);
             Ok(())
         })
@@ -306,21 +989,16 @@ fn get(client: &api::Client, opts: Opts, cmd: args::Get) -> Result<()> {
     api::Get::builder()
         .slug(cmd.slug)
         .build()
-        .run(client, opts, |post| {
+        .run(client, opts, |mut post| {
             if let Some(file) = cmd.print.output {
-                match file {
-                    OutputFile::File(path) | OutputFile::Stdout(Some(path)) => {
-                        let mut file = File::create(path)?;
-                        write!(file, "{post}")?;
-                        file.flush()?;
-                    }
-                    OutputFile::Stdout(None) => {
-                        let mut stdout = std::io::stdout().lock();
-                        write!(stdout, "{post}")?;
-                        stdout.flush()?;
+                if cmd.wiki_links {
+                    if let Some(body) = &post.body {
+                        let posts = client.call(api::List {})?;
+                        let blog = Blog::new(&posts, []);
+                        post.body = Some(wikilinks::translate(body, &blog, &posts));
                     }
                 }
-                return Ok(());
+                return write_output(file, post);
             }
 
             print_post(post, !cmd.print.body, !cmd.print.no_body);
@@ -328,13 +1006,32 @@ fn get(client: &api::Client, opts: Opts, cmd: args::Get) -> Result<()> {
         })
 }
 
-fn update(client: &api::Client, opts: Opts, cmd: args::Update) -> Result<()> {
-    let post = PostInput::from(cmd.title, cmd.new_slug, cmd.published_at, cmd.body)?;
+fn update(client: &api::Client, opts: Opts, config: &Config, cmd: args::Update) -> Result<()> {
+    let published_at = cmd
+        .published_at
+        .as_deref()
+        .map(|date| parse_date(client, config, date))
+        .transpose()?;
+    let mut post = PostInput::from(
+        cmd.title,
+        cmd.new_slug,
+        published_at,
+        cmd.body.clone(),
+        config.timezone,
+    )?;
+    resolve_date(client, config, &mut post)?;
     let slug = cmd.slug.or_else(|| post.slug.clone()).ok_or_eyre(concat!(
         "The slug is required to update a post. ",
         "It can be provided via the --slug flag, ",
         "or as the slug key in the post frontmatter.",
     ))?;
+    post.render(config)?;
+    series::keep_on_update(client, opts, &mut post, &slug)?;
+    wikilinks::resolve(client, opts, &mut post, cmd.body.as_ref())?;
+
+    if let Some(new_slug) = post.slug.as_deref().filter(|s| *s != slug) {
+        slug::preflight_rename(client, opts, new_slug, &slug, cmd.abort_on_collision)?;
+    }
 
     api::Update::builder()
         .slug(slug)
@@ -356,6 +1053,25 @@ fn delete(client: &api::Client, opts: Opts, cmd: args::Delete) -> Result<()> {
         .run(client, opts, |()| Ok(()))
 }
 
+fn slug(client: &api::Client, opts: Opts, cmd: &args::Slug) -> Result<()> {
+    api::List {}.run(client, opts, |posts| {
+        match slug::predict(&cmd.title, posts) {
+            slug::Prediction::Free(slug) => println!("{slug}"),
+            slug::Prediction::Taken(slug, post) => {
+                println!("{slug}");
+                warn!(
+                    post = post.title.as_deref().unwrap_or_default(),
+                    "The slug is already used by another post, mataroa will add a random suffix to it"
+                );
+            }
+            slug::Prediction::Random => {
+                warn!("Cannot predict the slug, mataroa will generate a random one");
+            }
+        }
+        Ok(())
+    })
+}
+
 fn batch(client: &api::Client, cmd: &args::Batch) -> Result<()> {
     let batch = cmd.batch.read_to_string()?;
     batch
@@ -391,6 +1107,79 @@ fn batch(client: &api::Client, cmd: &args::Batch) -> Result<()> {
     Ok(())
 }
 
+fn preview(client: &api::Client, opts: Opts, config: &Config, cmd: args::Preview) -> Result<()> {
+    if cmd.serve {
+        let root = Path::new(&cmd.source);
+        if !root.exists() {
+            bail!("'{}' does not exist", root.display());
+        }
+        return serve::run(root, &cmd.addr, config);
+    }
+
+    let output = cmd.output.unwrap_or(OutputFile::Stdout(None));
+
+    if cmd.source == "-" || Path::new(&cmd.source).exists() {
+        let file = InputFile::from(cmd.source.as_ref());
+        let mut post = PostInput::from(None, None, None, Some(file), config.timezone)?;
+        post.render(config)?;
+        let page = preview::render(preview::Page::from(&post));
+        return write_output(output, page);
+    }
+
+    api::Get::builder()
+        .slug(cmd.source)
+        .build()
+        .run(client, opts, |post| {
+            let page = preview::render(preview::Page::from(&post));
+            write_output(output, page)
+        })
+}
+
+fn date(client: &api::Client, config: &Config, cmd: &args::Date) -> Result<()> {
+    match parse_date(client, config, &cmd.expr.join(" "))? {
+        PublishDate::Date(date) => println!("{}", date.format("%Y-%m-%d (%A)")),
+        PublishDate::Draft => println!("draft"),
+    }
+    Ok(())
+}
+
+/// Parses a date that is given on the command line, which can be relative
+/// to the date of another post.
+fn parse_date(client: &api::Client, config: &Config, date: &str) -> Result<PublishDate> {
+    dateformat::parse_with(date, config.timezone, |slug| {
+        let post = client
+            .call(api::Get::builder().slug(slug.to_owned()).build())
+            .wrap_err_with(|| format!("Failed to look up the date of the post '{slug}'"))?;
+        Ok(post.publish_date())
+    })
+}
+
+/// Looks up the `published_at` date of a post file that is relative to
+/// another post.
+fn resolve_date(client: &api::Client, config: &Config, post: &mut PostInput) -> Result<()> {
+    if let Some(date) = post.published_after.take() {
+        let date = parse_date(client, config, &date).wrap_err("Invalid `published_at`")?;
+        post.published_at = Some(date);
+    }
+    Ok(())
+}
+
+fn write_output(file: OutputFile, content: impl Display) -> Result<()> {
+    match file {
+        OutputFile::File(path) | OutputFile::Stdout(Some(path)) => {
+            let mut file = File::create(path)?;
+            write!(file, "{content}")?;
+            file.flush()?;
+        }
+        OutputFile::Stdout(None) => {
+            let mut stdout = std::io::stdout().lock();
+            write!(stdout, "{content}")?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
 pub struct Post {
     pub slug: String,
@@ -400,6 +1189,16 @@ pub struct Post {
     pub url: Option<String>,
 }
 
+impl Post {
+    /// The publish date of the post, or `Draft` if it doesn't have one.
+    pub fn publish_date(&self) -> PublishDate {
+        self.published_at
+            .as_deref()
+            .and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            .map_or(PublishDate::Draft, PublishDate::Date)
+    }
+}
+
 fn print_post(post: Post, meta: bool, body: bool) {
     if meta {
         print!("{}", post.slug);
@@ -444,13 +1243,31 @@ impl Display for Post {
     }
 }
 
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct PostInput {
     title: Option<String>,
     slug: Option<String>,
-    #[serde(default, with = "dateformat")]
+    /// Parsed separately, since relative dates depend on the time zone.
+    #[serde(skip)]
     published_at: Option<PublishDate>,
+    /// A `published_at` that is relative to another post, which is looked
+    /// up when the post is created or updated.
+    #[serde(skip)]
+    published_after: Option<String>,
+    /// Written back after creating a post, for reference only.
+    url: Option<String>,
+    /// Additional variables for templates in the body.
+    #[serde(default)]
+    vars: BTreeMap<String, String>,
+    /// Transforms for this post, overriding those of the config.
+    #[serde(default)]
+    transforms: Transforms,
+    /// The series the post belongs to.
+    series: Option<series::Series>,
+    /// The date on which the post expires.
+    #[serde(skip)]
+    expires: Option<PublishDate>,
     #[serde(skip)]
     body: Option<String>,
 }
@@ -461,10 +1278,11 @@ impl PostInput {
         slug: Option<String>,
         published_at: Option<PublishDate>,
         body: Option<InputFile>,
+        tz: Option<Tz>,
     ) -> Result<Self> {
         let mut post = Self::new(title, slug, published_at);
         if let Some(body) = body {
-            post.update(&body)?;
+            post.update(&body, tz)?;
         }
         Ok(post)
     }
@@ -478,12 +1296,23 @@ impl PostInput {
             title,
             slug,
             published_at,
+            published_after: None,
+            url: None,
+            vars: BTreeMap::new(),
+            transforms: Transforms {
+                shift_headings: None,
+                toc: None,
+                footnotes: None,
+                typography: None,
+            },
+            series: None,
+            expires: None,
             body: None,
         }
     }
 
-    fn update(&mut self, file: &InputFile) -> Result<()> {
-        let new = Self::from_file(file)?;
+    fn update(&mut self, file: &InputFile, tz: Option<Tz>) -> Result<()> {
+        let new = Self::from_file(file, tz)?;
         if self.title.is_none() {
             self.title = new.title;
         }
@@ -492,19 +1321,58 @@ impl PostInput {
         }
         if self.published_at.is_none() {
             self.published_at = new.published_at;
+            self.published_after = new.published_after;
         }
         if self.body.is_none() {
             self.body = new.body;
         }
+        if self.vars.is_empty() {
+            self.vars = new.vars;
+        }
+        self.transforms = self.transforms.or(new.transforms);
+        if self.series.is_none() {
+            self.series = new.series;
+        }
+        if self.expires.is_none() {
+            self.expires = new.expires;
+        }
+        Ok(())
+    }
+
+    /// Expands the templates in the body and applies the transforms to it,
+    /// and records the series and the expiry date of the post in it,
+    /// which is done to every post file before it is published or previewed.
+    fn render(&mut self, config: &Config) -> Result<()> {
+        template::expand(self, config)?;
+        transform::apply_to(self, config);
+        series::apply_to(self);
+        expire::apply_to(self);
         Ok(())
     }
 
-    fn from_file(file: &InputFile) -> Result<Self> {
-        let file = file.read_to_string()?;
-        Self::from_markdown(&file)
+    fn from_file(file: &InputFile, tz: Option<Tz>) -> Result<Self> {
+        let content = file.read_to_string()?;
+        let path = match file {
+            InputFile::File(path) => Some(path.as_path()),
+            InputFile::Stdin(_) => None,
+        };
+        Self::from_markdown(&content, path, tz)
     }
 
-    fn from_markdown(content: &str) -> Result<Self> {
+    /// Parses a post file. Includes in the body are resolved relative to
+    /// `path`, or to the current directory if there is no path.
+    fn from_markdown(content: &str, path: Option<&Path>, tz: Option<Tz>) -> Result<Self> {
+        let mut post = Self::parse(content, tz)?;
+        if let Some(body) = post.body.take() {
+            post.body = Some(include::expand(&body, path)?).filter(|s| !s.is_empty());
+        }
+        Ok(post)
+    }
+
+    /// Parses a post file as it is, without resolving includes.
+    /// Relative dates in the frontmatter are relative to today in the time
+    /// zone `tz` of the blog.
+    fn parse(content: &str, tz: Option<Tz>) -> Result<Self> {
         enum Lang {
             Yaml,
             Json,
@@ -546,14 +1414,39 @@ impl PostInput {
             Some(l) => bail!("'{}' is not supported, only yaml and json are.", l),
         };
 
-        let meta: Self = match lang {
+        let mut meta: serde_json::Map<String, serde_json::Value> = match lang {
             Lang::Yaml => serde_yaml::from_str(&cb.source)?,
             Lang::Json => serde_json::from_str(&cb.source)?,
         };
+        let published_at = meta.remove("published_at");
+        let published_after = match &published_at {
+            Some(serde_json::Value::String(value)) if dateformat::is_post_relative(value) => {
+                Some(value.clone())
+            }
+            _ => None,
+        };
+        let published_at = if published_after.is_some() {
+            None
+        } else {
+            dateformat::from_value("published_at", published_at, tz)?
+        };
+        let expires = meta.remove("expires");
+        // a relative date would move on every update of the post
+        if let Some(serde_json::Value::String(value)) = &expires {
+            ensure!(
+                dateformat::is_absolute(value),
+                "`expires` must be a date like \"2024-06-30\", not '{value}'"
+            );
+        }
+        let expires = dateformat::from_value("expires", expires, tz)?;
+        let meta: Self = serde_json::from_value(meta.into())?;
 
         Ok(Self {
             body,
             title: meta.title.or(frontmatter.title),
+            published_at,
+            published_after,
+            expires,
             ..meta
         })
     }
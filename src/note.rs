@@ -0,0 +1,237 @@
+use std::{collections::BTreeMap, io::Read as _};
+
+use kommandozeile::{
+    color_eyre::eyre::{ensure, WrapErr as _},
+    tracing::warn,
+    Result,
+};
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args,
+    config::Config,
+    dateformat::{self, PublishDate},
+    print_post, slug, template, PostInput,
+};
+
+/// Titles that are longer than this are shortened.
+const MAX_TITLE: usize = 80;
+
+/// Creates a note, or appends it to the daily post.
+pub fn run(client: &api::Client, opts: Opts, config: &Config, cmd: &args::Note) -> Result<()> {
+    let text = if cmd.text.is_empty() {
+        let mut text = String::new();
+        let _ = std::io::stdin()
+            .read_to_string(&mut text)
+            .wrap_err("Failed to read the note from stdin")?;
+        text
+    } else {
+        cmd.text.join(" ")
+    };
+    let text = text.trim();
+    ensure!(!text.is_empty(), "The note is empty");
+
+    let now = dateformat::now(config.timezone);
+    let time = now.format("%H:%M").to_string();
+    let vars = [
+        ("text", text.to_owned()),
+        ("first_line", first_line(text).to_owned()),
+        ("first_sentence", first_sentence(text)),
+        ("date", now.format("%Y-%m-%d").to_string()),
+        ("time", time.clone()),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_owned(), value))
+    .collect::<BTreeMap<_, _>>();
+
+    if cmd.append_to_daily {
+        let title = template::render(&config.note.daily_title, &vars, config)
+            .wrap_err("Invalid note.daily_title in the config")?;
+        return append_to_daily(
+            client,
+            opts,
+            config,
+            title.trim(),
+            &format!("**{time}** {text}"),
+        );
+    }
+
+    let title = template::render(&config.note.title, &vars, config)
+        .wrap_err("Invalid note.title in the config")?;
+    let title = shorten(title.trim());
+    let slug = template::render(&config.note.slug, &vars, config)
+        .wrap_err("Invalid note.slug in the config")?;
+    let slug = slug::slugify(&slug);
+
+    let body = render(text, config)?;
+    // mataroa derives the slug from the title, so it is changed afterwards
+    let slug = if opts.dry_run {
+        slug
+    } else {
+        free_slug(&slug, &client.call(api::List {})?)
+    };
+
+    api::Create::builder()
+        .title(title)
+        .body(Some(body))
+        .published_at(Some(PublishDate::Date(now.date())))
+        .build()
+        .run(client, opts, |post| {
+            if slug.is_empty() || post.slug == slug {
+                print_post(post, true, false);
+                return Ok(());
+            }
+            api::Update::builder()
+                .slug(post.slug)
+                .title(post.title)
+                .updated_slug(Some(slug))
+                .build()
+                .run(client, opts, |post| {
+                    print_post(post, true, false);
+                    Ok(())
+                })
+        })
+}
+
+fn append_to_daily(
+    client: &api::Client,
+    opts: Opts,
+    config: &Config,
+    title: &str,
+    entry: &str,
+) -> Result<()> {
+    let slug = slug::slugify(title);
+    if opts.dry_run {
+        warn!("The daily post is not looked up with --dry-run, so only the entry is shown");
+        return api::Update::builder()
+            .slug(slug)
+            .body(Some(render(entry, config)?))
+            .build()
+            .run(client, opts, |_| Ok(()));
+    }
+    let daily = match client.call(api::Get::builder().slug(slug.clone()).build()) {
+        Ok(daily) => Some(daily),
+        Err(e) if matches!(e.downcast_ref(), Some(ureq::Error::Status(404, _))) => None,
+        Err(e) => return Err(e),
+    };
+
+    let Some(daily) = daily else {
+        return api::Create::builder()
+            .title(title.to_owned())
+            .body(Some(render(entry, config)?))
+            .published_at(Some(PublishDate::Date(dateformat::today(config.timezone))))
+            .build()
+            .run(client, opts, |post| {
+                print_post(post, true, false);
+                Ok(())
+            });
+    };
+
+    let body = daily.body.unwrap_or_default();
+    let body = format!("{}\n\n{}", body.trim_end(), render(entry, config)?);
+    api::Update::builder()
+        .slug(slug)
+        .title(daily.title)
+        .body(Some(body.trim_start().to_owned()))
+        .build()
+        .run(client, opts, |post| {
+            print_post(post, true, false);
+            Ok(())
+        })
+}
+
+/// Expands the templates and applies the transforms, like for any other post.
+fn render(text: &str, config: &Config) -> Result<String> {
+    let mut post = PostInput {
+        body: Some(text.to_owned()),
+        ..PostInput::default()
+    };
+    post.render(config)?;
+    Ok(post.body.unwrap_or_default())
+}
+
+/// `slug`, or `slug` with a number appended if another post already uses it.
+fn free_slug(slug: &str, posts: &[crate::Post]) -> String {
+    let taken = |s: &str| posts.iter().any(|p| p.slug == s);
+    if !taken(slug) {
+        return slug.to_owned();
+    }
+    // one of these is free, since there are not more posts than that
+    (2..=posts.len() + 2)
+        .map(|n| format!("{slug}-{n}"))
+        .find(|s| !taken(s))
+        .unwrap_or_default()
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default().trim()
+}
+
+/// The first sentence of the first line, without a trailing period.
+fn first_sentence(text: &str) -> String {
+    let line = first_line(text);
+    let end = line
+        .char_indices()
+        .find(|&(idx, c)| {
+            matches!(c, '.' | '!' | '?')
+                && line[idx + 1..]
+                    .chars()
+                    .next()
+                    .map_or(true, char::is_whitespace)
+        })
+        .map_or(line.len(), |(idx, c)| idx + c.len_utf8());
+    line[..end].trim_end_matches('.').to_owned()
+}
+
+/// Cuts `title` at a word boundary if it is too long.
+fn shorten(title: &str) -> String {
+    if title.chars().count() <= MAX_TITLE {
+        return title.to_owned();
+    }
+    let mut short = String::new();
+    for word in title.split_whitespace() {
+        if short.chars().count() + word.chars().count() + 1 > MAX_TITLE {
+            break;
+        }
+        if !short.is_empty() {
+            short.push(' ');
+        }
+        short.push_str(word);
+    }
+    short.push('…');
+    short
+}
+
+#[test]
+fn note_titles() {
+    let text = "Shipped the new release. It has notes!\nSecond line.";
+    assert_eq!(first_line(text), "Shipped the new release. It has notes!");
+    assert_eq!(first_sentence(text), "Shipped the new release");
+    assert_eq!(
+        first_sentence("What about v1.2? Later."),
+        "What about v1.2?"
+    );
+    assert_eq!(first_sentence("no punctuation"), "no punctuation");
+}
+
+#[test]
+fn shorten_long_titles() {
+    let long = "word ".repeat(30);
+    let short = shorten(long.trim());
+    assert!(short.chars().count() <= MAX_TITLE + 1);
+    assert!(short.ends_with("word…"));
+}
+
+#[test]
+fn free_note_slugs() {
+    let post = |slug: &str| crate::Post {
+        slug: slug.to_owned(),
+        title: None,
+        body: None,
+        published_at: None,
+        url: None,
+    };
+    let posts = [post("2015-10-21-1629"), post("2015-10-21-1629-2")];
+    assert_eq!(free_slug("2015-10-21-1629", &posts), "2015-10-21-1629-3");
+    assert_eq!(free_slug("2015-10-21-1630", &posts), "2015-10-21-1630");
+}
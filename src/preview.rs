@@ -0,0 +1,269 @@
+use std::{fmt::Write as _, ops::Range};
+
+use pulldown_cmark::{escape::escape_html, html, Event, Options, Parser, Tag};
+use unicode_normalization::UnicodeNormalization as _;
+
+use crate::{dateformat::PublishDate, Post, PostInput};
+
+/// Stylesheet loosely modelled after the default mataroa theme.
+const STYLE: &str = r"
+:root { color-scheme: light dark; }
+body {
+  max-width: 700px;
+  margin: 0 auto;
+  padding: 16px;
+  font-family: sans-serif;
+  font-size: 18px;
+  line-height: 1.6;
+  color: #111;
+  background: #fff;
+}
+@media (prefers-color-scheme: dark) {
+  body { color: #eee; background: #111; }
+  a { color: #8cb4ff; }
+  pre, code { background: #222; }
+}
+h1, h2, h3, h4, h5, h6 { line-height: 1.25; }
+header h1 { margin-bottom: 0; }
+header time { display: block; margin: 4px 0 32px; color: #777; font-size: 16px; }
+a { color: #0063ff; }
+img { max-width: 100%; }
+pre { padding: 12px; overflow-x: auto; background: #f5f5f5; }
+code { font-size: 15px; background: #f5f5f5; }
+pre code { background: none; }
+blockquote { margin: 0; padding-left: 16px; border-left: 3px solid #999; color: #555; }
+table { border-collapse: collapse; }
+th, td { padding: 4px 8px; border: 1px solid #999; }
+hr { border: 0; border-top: 1px solid #999; }
+.draft { color: #c00; }
+.error { padding: 12px; border: 2px solid #c00; white-space: pre-wrap; }
+";
+
+/// The parts of a post that make up the rendered page.
+#[derive(Copy, Clone, Debug)]
+pub struct Page<'a> {
+    pub title: Option<&'a str>,
+    pub published_at: Option<PublishDate>,
+    pub body: Option<&'a str>,
+}
+
+impl<'a> From<&'a Post> for Page<'a> {
+    fn from(post: &'a Post) -> Self {
+        Self {
+            title: post.title.as_deref(),
+            published_at: Some(post.publish_date()),
+            body: post.body.as_deref(),
+        }
+    }
+}
+
+impl<'a> From<&'a PostInput> for Page<'a> {
+    fn from(post: &'a PostInput) -> Self {
+        Self {
+            title: post.title.as_deref(),
+            published_at: post.published_at,
+            body: post.body.as_deref(),
+        }
+    }
+}
+
+/// Markdown extensions that mirror the ones enabled by mataroa.
+pub fn options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+}
+
+/// The ranges of the code spans and code blocks in `markdown`.
+pub fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(markdown, options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Renders the page as a standalone HTML document.
+pub fn render(page: Page<'_>) -> String {
+    let mut article = String::with_capacity(4096);
+    render_article(&mut article, page);
+    document(page.title.unwrap_or("Untitled"), &article, None)
+}
+
+/// Wraps the rendered `content` into a standalone HTML document.
+/// The optional `script` is added to the end of the body.
+pub fn document(title: &str, content: &str, script: Option<&str>) -> String {
+    let mut out = String::with_capacity(content.len() + STYLE.len() + 512);
+    out.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n");
+    out.push_str("<meta charset=\"utf-8\">\n");
+    out.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    out.push_str("<title>");
+    push_escaped(&mut out, title);
+    out.push_str("</title>\n<style>");
+    out.push_str(STYLE);
+    out.push_str("</style>\n</head>\n<body>\n");
+    out.push_str(content);
+    if let Some(script) = script {
+        out.push_str("<script>");
+        out.push_str(script);
+        out.push_str("</script>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Renders the header and body of the page into `out`.
+pub fn render_article(out: &mut String, page: Page<'_>) {
+    out.push_str("<article>\n<header>\n");
+    if let Some(title) = page.title {
+        out.push_str("<h1>");
+        push_escaped(out, title);
+        out.push_str("</h1>\n");
+    }
+    match page.published_at {
+        Some(PublishDate::Date(date)) => {
+            let _ = writeln!(
+                out,
+                "<time datetime=\"{}\">{}</time>",
+                date.format("%Y-%m-%d"),
+                date.format("%B %-d, %Y"),
+            );
+        }
+        Some(PublishDate::Draft) | None => {
+            out.push_str("<time class=\"draft\">Draft</time>\n");
+        }
+    }
+    out.push_str("</header>\n");
+
+    if let Some(body) = page.body {
+        let parser = Parser::new_ext(body, options());
+        html::push_html(out, with_heading_ids(parser).into_iter());
+    }
+
+    out.push_str("</article>\n");
+}
+
+pub fn push_escaped(out: &mut String, text: &str) {
+    // writing into a String cannot fail
+    let _ = escape_html(&mut *out, text);
+}
+
+/// Adds `id` attributes to headings, like the toc extension of mataroa does.
+fn with_heading_ids<'a>(parser: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let events = parser.collect::<Vec<_>>();
+    let mut used = Vec::new();
+
+    events
+        .iter()
+        .enumerate()
+        .map(|(idx, event)| match event {
+            Event::Start(Tag::Heading(level, None, classes)) => {
+                let id = unique_anchor(&heading_text(&events[idx + 1..]), &mut used);
+                let mut tag = format!("<{level} id=\"{id}\"");
+                if !classes.is_empty() {
+                    tag.push_str(" class=\"");
+                    push_escaped(&mut tag, &classes.join(" "));
+                    tag.push('"');
+                }
+                tag.push('>');
+                Event::Html(tag.into())
+            }
+            Event::End(Tag::Heading(level, None, _)) => Event::Html(format!("</{level}>\n").into()),
+            event => event.clone(),
+        })
+        .collect()
+}
+
+fn heading_text(events: &[Event<'_>]) -> String {
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::End(Tag::Heading(..)) => break,
+            Event::Text(t) | Event::Code(t) => text.push_str(t),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// `anchor(text)`, or with `_1`, `_2`, etc. appended if that is already in `used`
+/// or empty, like the toc extension of python-markdown does.
+pub fn unique_anchor(text: &str, used: &mut Vec<String>) -> String {
+    let base = anchor(text);
+    let mut id = base.clone();
+    let mut n = 1;
+    while id.is_empty() || used.contains(&id) {
+        id = format!("{base}_{n}");
+        n += 1;
+    }
+    used.push(id.clone());
+    id
+}
+
+/// Mirrors the slugify function of the python-markdown toc extension: The
+/// text is normalized to ASCII, everything that is not alphanumeric, an
+/// underscore, a hyphen, or whitespace is dropped, and runs of whitespace and
+/// hyphens are replaced by a single hyphen.
+pub fn anchor(text: &str) -> String {
+    let text = text
+        .nfkd()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-') || c.is_ascii_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let mut anchor = String::with_capacity(text.len());
+    let mut separator = false;
+    for c in text.trim().chars() {
+        if c == '-' || c.is_ascii_whitespace() {
+            separator = true;
+            continue;
+        }
+        if separator {
+            anchor.push('-');
+            separator = false;
+        }
+        anchor.push(c);
+    }
+    if separator {
+        anchor.push('-');
+    }
+    anchor
+}
+
+#[test]
+fn heading_anchors() {
+    assert_eq!(anchor("Getting Started"), "getting-started");
+    assert_eq!(anchor("  What's new in v2.0?  "), "whats-new-in-v20");
+    assert_eq!(anchor("Crème brûlée"), "creme-brulee");
+    assert_eq!(anchor("snake_case -- and  dashes"), "snake_case-and-dashes");
+    assert_eq!(anchor("-leading"), "-leading");
+    assert_eq!(anchor("🎉"), "");
+}
+
+#[test]
+fn unique_heading_anchors() {
+    let mut used = Vec::new();
+    assert_eq!(unique_anchor("Intro", &mut used), "intro");
+    assert_eq!(unique_anchor("Intro", &mut used), "intro_1");
+    assert_eq!(unique_anchor("intro!", &mut used), "intro_2");
+    assert_eq!(unique_anchor("🎉", &mut used), "_1");
+    assert_eq!(unique_anchor("🎉", &mut used), "_2");
+}
+
+#[test]
+fn render_page() {
+    let page = Page {
+        title: Some("Fish & <Chips>"),
+        published_at: None,
+        body: Some("## Intro\n\nText with ~~strike~~.\n\n## *Intro*\n"),
+    };
+    let html = render(page);
+    assert!(html.contains("<title>Fish &amp; &lt;Chips&gt;</title>"));
+    assert!(html.contains("<h1>Fish &amp; &lt;Chips&gt;</h1>"));
+    assert!(html.contains("<time class=\"draft\">Draft</time>"));
+    assert!(html.contains("<h2 id=\"intro\">Intro</h2>"));
+    assert!(html.contains("<h2 id=\"intro_1\"><em>Intro</em></h2>"));
+    assert!(html.contains("<del>strike</del>"));
+}
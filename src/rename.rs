@@ -0,0 +1,156 @@
+use kommandozeile::{
+    color_eyre::eyre::{bail, OptionExt as _},
+    tracing::{error, warn},
+    Result,
+};
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args,
+    links::{self, Blog},
+    Post,
+};
+
+/// An update of the body of a post, because it links to the renamed post.
+#[derive(Clone, Debug)]
+struct Rewrite {
+    /// The slug of the post after the rename.
+    slug: String,
+    original: String,
+    body: String,
+}
+
+/// Renames a post and rewrites all links to it in the bodies of all posts.
+///
+/// The rename and the rewrites are printed as a plan before they are applied.
+/// With `--dry-run`, the posts are still read to compute the plan, but
+/// nothing is changed.
+/// If any update fails, all previous updates are reverted.
+pub fn run(client: &api::Client, opts: Opts, cmd: &args::Rename) -> Result<()> {
+    if cmd.old == cmd.new {
+        bail!("The old and the new slug are the same");
+    }
+
+    let posts = client.all_posts()?;
+    let blog = Blog::new(&posts, cmd.domain.iter().map(String::as_str));
+
+    let renamed = posts
+        .iter()
+        .find(|p| p.slug == cmd.old)
+        .ok_or_eyre(format!("There is no post with the slug '{}'", cmd.old))?;
+    if let Some(taken) = posts.iter().find(|p| p.slug == cmd.new) {
+        bail!(
+            "The slug '{}' is already used by the post '{}'",
+            cmd.new,
+            taken.title.as_deref().unwrap_or_default()
+        );
+    }
+
+    println!(
+        "Rename {} -> {} ({})",
+        cmd.old,
+        cmd.new,
+        renamed.title.as_deref().unwrap_or_default()
+    );
+
+    let rewrites = plan(&posts, &blog, &cmd.old, &cmd.new);
+    if rewrites.is_empty() {
+        println!("No links to rewrite");
+    }
+
+    apply(client, opts, cmd, &rewrites)
+}
+
+/// Finds all links to the `old` slug and prints how they will be rewritten.
+fn plan(posts: &[Post], blog: &Blog, old: &str, new: &str) -> Vec<Rewrite> {
+    posts
+        .iter()
+        .filter_map(|post| {
+            let original = post.body.as_deref()?;
+            let found = links::find(original, blog);
+            let found = found.iter().filter(|l| l.slug == old).collect::<Vec<_>>();
+            if found.is_empty() {
+                return None;
+            }
+
+            let slug = if post.slug == old { new } else { &post.slug };
+            for link in &found {
+                println!(
+                    "  {slug}:{}: {} -> {}",
+                    link.line,
+                    link.dest,
+                    link.with_slug(new)
+                );
+            }
+
+            let body = links::rewrite(original, found.into_iter().map(|l| (l, l.with_slug(new))));
+            Some(Rewrite {
+                slug: slug.to_owned(),
+                original: original.to_owned(),
+                body,
+            })
+        })
+        .collect()
+}
+
+fn apply(client: &api::Client, opts: Opts, cmd: &args::Rename, rewrites: &[Rewrite]) -> Result<()> {
+    api::Update::builder()
+        .slug(cmd.old.clone())
+        .updated_slug(Some(cmd.new.clone()))
+        .build()
+        .run(client, opts, |_| Ok(()))?;
+
+    for (done, rewrite) in rewrites.iter().enumerate() {
+        let result = api::Update::builder()
+            .slug(rewrite.slug.clone())
+            .body(Some(rewrite.body.clone()))
+            .build()
+            .run(client, opts, |_| Ok(()));
+
+        if let Err(err) = result {
+            error!(post = rewrite.slug, "Failed to rewrite links, rolling back");
+            rollback(client, cmd, &rewrites[..done]);
+            return Err(err);
+        }
+    }
+
+    if !opts.dry_run {
+        println!(
+            "Renamed {} -> {} and rewrote links in {} posts",
+            cmd.old,
+            cmd.new,
+            rewrites.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Reverts the given rewrites and the rename itself.
+/// Failures are only logged, so that as much as possible is reverted.
+fn rollback(client: &api::Client, cmd: &args::Rename, rewrites: &[Rewrite]) {
+    for rewrite in rewrites.iter().rev() {
+        let revert = api::Update::builder()
+            .slug(rewrite.slug.clone())
+            .body(Some(rewrite.original.clone()))
+            .build();
+        if let Err(err) = client.call(revert) {
+            warn!(
+                post = rewrite.slug,
+                ?err,
+                "Failed to revert the rewritten links"
+            );
+        }
+    }
+
+    let revert = api::Update::builder()
+        .slug(cmd.new.clone())
+        .updated_slug(Some(cmd.old.clone()))
+        .build();
+    if let Err(err) = client.call(revert) {
+        warn!(
+            ?err,
+            "Failed to revert the rename of {} -> {}", cmd.old, cmd.new
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use kommandozeile::{
+    color_eyre::eyre::{bail, ensure, OptionExt as _, WrapErr as _},
+    tracing::warn,
+    InputFile, Result,
+};
+
+use crate::{
+    api::{self, Opts},
+    args,
+    config::Config,
+    dateformat::{self, PublishDate},
+    frontmatter, slug, template, PostInput,
+};
+
+/// Writes a new post file from the template that is given with `--template`,
+/// and creates the post from it after editing, if `--edit` is given.
+pub fn run(client: &api::Client, opts: Opts, config: &Config, cmd: &args::Create) -> Result<()> {
+    let name = cmd.template.as_deref().unwrap_or_default();
+    let template_path = config.templates_dir()?.join(format!("{name}.md"));
+    let template = fs::read_to_string(&template_path).wrap_err_with(|| {
+        format!(
+            "There is no template '{name}' at '{}'",
+            template_path.display()
+        )
+    })?;
+    let mut post = PostInput::parse(&template, config.timezone)
+        .wrap_err_with(|| format!("Invalid template '{}'", template_path.display()))?;
+
+    let published_at = if let Some(date) = cmd.published_at.as_deref() {
+        Some(crate::parse_date(client, config, date)?)
+    } else {
+        crate::resolve_date(client, config, &mut post)?;
+        post.published_at
+    };
+    let title = cmd.title.clone().or(post.title);
+
+    let today = dateformat::today(config.timezone);
+    let date = match published_at {
+        Some(PublishDate::Date(date)) => date,
+        _ => today,
+    };
+    let mut vars = BTreeMap::new();
+    let _ = vars.insert(String::from("today"), today.format("%Y-%m-%d").to_string());
+    let _ = vars.insert(String::from("date"), date.format("%Y-%m-%d").to_string());
+    if let Some(prefix) = title.as_deref().and_then(count_prefix) {
+        if opts.dry_run {
+            warn!("The posts are not looked up with --dry-run, so the count is kept");
+        } else {
+            let posts = client.call(api::List {})?;
+            let titles = posts.iter().filter_map(|p| p.title.as_deref());
+            let count = next_count(prefix, titles);
+            let _ = vars.insert(String::from("count"), count.to_string());
+        }
+    }
+
+    let mut fields = Vec::new();
+    let title = title.map(|title| template::expand_known(&title, &vars));
+    if let (Some(title), Some(_)) = (&title, &cmd.title) {
+        fields.push(("title", title.clone()));
+    }
+    match published_at {
+        Some(PublishDate::Date(date)) => {
+            fields.push(("published_at", date.format("%Y-%m-%d").to_string()));
+        }
+        Some(PublishDate::Draft) => fields.push(("published_at", String::new())),
+        None => {}
+    }
+
+    let content = template::expand_known(&template, &vars);
+    let fields = fields
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .collect::<Vec<_>>();
+    let content = frontmatter::set(&content, &fields)?;
+
+    let path = match cmd.body.clone() {
+        Some(InputFile::File(path)) => path,
+        Some(InputFile::Stdin(_)) => {
+            bail!("A new post from a template can only be written to a file")
+        }
+        None => {
+            let title = title.ok_or_eyre(concat!(
+                "The template has no title to name the file after, ",
+                "either give a file name or a title with --title"
+            ))?;
+            PathBuf::from(format!("{}.md", slug::slugify(&strip_expressions(&title))))
+        }
+    };
+    ensure!(!path.exists(), "'{}' already exists", path.display());
+
+    if opts.dry_run {
+        println!("Would write {}:\n\n{content}", path.display());
+        return Ok(());
+    }
+    fs::write(&path, content).wrap_err_with(|| format!("Failed to write '{}'", path.display()))?;
+    println!("Wrote {}", path.display());
+
+    if !cmd.edit {
+        return Ok(());
+    }
+
+    edit(&path)?;
+    let content = fs::read_to_string(&path)?;
+    ensure!(
+        !content.trim().is_empty(),
+        "'{}' is empty, the post is not created",
+        path.display()
+    );
+
+    crate::create(
+        client,
+        opts,
+        config,
+        args::Create {
+            body: Some(InputFile::File(path)),
+            title: None,
+            published_at: None,
+            no_write_back: cmd.no_write_back,
+            abort_on_collision: cmd.abort_on_collision,
+            template: None,
+            edit: false,
+        },
+    )
+}
+
+/// Opens `path` in the editor of the user and waits for it to exit.
+fn edit(path: &Path) -> Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+
+    let mut tokens = shlex::Shlex::new(&editor);
+    let program = tokens.next().ok_or_eyre("The editor command is empty")?;
+    let status = Command::new(program)
+        .args(tokens)
+        .arg(path)
+        .status()
+        .wrap_err_with(|| format!("Failed to start the editor '{editor}'"))?;
+
+    ensure!(
+        status.success(),
+        "The editor '{editor}' failed ({status}), the post is not created"
+    );
+    Ok(())
+}
+
+/// The part of a title before its `{{ count }}` expression,
+/// e.g. `Weekly Notes #` for `Weekly Notes #{{ count }}`.
+fn count_prefix(title: &str) -> Option<&str> {
+    let mut at = 0;
+    while let Some(start) = title[at..].find("{{").map(|s| s + at) {
+        let end = title[start..].find("}}")? + start;
+        if title[start + 2..end].trim() == "count" {
+            return Some(&title[..start]);
+        }
+        at = end + 2;
+    }
+    None
+}
+
+/// `text` without the template expressions that are left in it,
+/// e.g. the `{{ count }}` that is not looked up with `--dry-run`.
+fn strip_expressions(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start + end + 2..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// One more than the highest number that follows `prefix` in the titles,
+/// or 1 if there is none.
+fn next_count<'a>(prefix: &str, titles: impl IntoIterator<Item = &'a str>) -> u64 {
+    titles
+        .into_iter()
+        .filter_map(|title| {
+            let rest = title.strip_prefix(prefix)?;
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            rest[..digits].parse::<u64>().ok()
+        })
+        .max()
+        .map_or(1, |highest| highest + 1)
+}
+
+#[test]
+fn fill_in_template() {
+    let prefix = count_prefix("Weekly Notes #{{ count }} ({{ today }})");
+    assert_eq!(prefix, Some("Weekly Notes #"));
+    assert_eq!(count_prefix("{{ title }}"), None);
+    assert_eq!(
+        strip_expressions("Weekly Notes #{{ count }} ({{ x }})"),
+        "Weekly Notes # ()"
+    );
+
+    let titles = [
+        "Weekly Notes #41 (a)",
+        "Weekly Notes #7",
+        "Other #50",
+        "Weekly Notes #x",
+    ];
+    assert_eq!(next_count("Weekly Notes #", titles), 42);
+    assert_eq!(next_count("Monthly Notes #", titles), 1);
+
+    let vars = [
+        (String::from("count"), String::from("42")),
+        (String::from("date"), String::from("2015-10-21")),
+    ]
+    .into();
+    assert_eq!(
+        template::expand_known(
+            "#{{ count }} on {{ date | date(\"%B %-d\") }}, {{ title }} \\{{ x }} {{ note(\"a\") }}",
+            &vars,
+        ),
+        "#42 on October 21, {{ title }} \\{{ x }} {{ note(\"a\") }}"
+    );
+}
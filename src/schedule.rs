@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike as _, Days, NaiveDate as Date, Weekday};
+use kommandozeile::{
+    color_eyre::eyre::{bail, ensure, WrapErr as _},
+    Result,
+};
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args,
+    config::Config,
+    dateformat::{self, PublishDate},
+    scheduler, Post,
+};
+
+/// Shows the editorial calendar, schedules drafts into the free slots
+/// with `--fill`, or queues a draft to be published with `--at`.
+pub fn run(client: &api::Client, opts: Opts, config: &Config, cmd: &args::Schedule) -> Result<()> {
+    if let Some(at) = &cmd.at {
+        let [slug] = cmd.drafts.as_slice() else {
+            bail!(
+                "--at schedules a single draft, but {} were given",
+                cmd.drafts.len()
+            );
+        };
+        return scheduler::add(client, opts, config, slug, at);
+    }
+    ensure!(
+        cmd.fill || cmd.drafts.is_empty(),
+        "Drafts can only be given with --fill or --at"
+    );
+
+    let posts = client.call(api::List {})?;
+    let today = dateformat::today(config.timezone);
+    let cadence = &config.schedule.cadence;
+
+    if cmd.fill {
+        return fill(client, opts, cadence, &posts, &cmd.drafts, today);
+    }
+
+    let weeks = Days::new(u64::from(cmd.weeks) * 7);
+    let from = today - weeks;
+    let mut until = today + weeks;
+
+    let mut calendar = BTreeMap::<Date, Vec<&Post>>::new();
+    for post in &posts {
+        if let PublishDate::Date(date) = post.publish_date() {
+            if date >= from && (date <= until || date > today) {
+                calendar.entry(date).or_default().push(post);
+            }
+        }
+    }
+    if let Some((&last, _)) = calendar.last_key_value() {
+        until = until.max(last);
+    }
+
+    let taken = taken(&posts);
+    for date in slots(cadence, &taken, today).take_while(|&d| d <= until) {
+        let _ = calendar.entry(date).or_default();
+    }
+
+    for (date, posts) in &calendar {
+        let day = date.format("%Y-%m-%d %a");
+        if posts.is_empty() {
+            println!("{day}  free");
+        }
+        for post in posts {
+            let state = if *date > today {
+                "scheduled"
+            } else {
+                "published"
+            };
+            print!("{day}  {state:<9}  {}", post.slug);
+            if let Some(title) = &post.title {
+                print!(": {title}");
+            }
+            println!();
+        }
+    }
+
+    let drafts = posts
+        .iter()
+        .filter(|p| p.publish_date() == PublishDate::Draft)
+        .map(|p| p.slug.as_str())
+        .collect::<Vec<_>>();
+    if !drafts.is_empty() {
+        println!();
+        println!("Drafts: {}", drafts.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Assigns the next free slots to the drafts, in order, and updates them.
+fn fill(
+    client: &api::Client,
+    opts: Opts,
+    cadence: &[Weekday],
+    posts: &[Post],
+    drafts: &[String],
+    today: Date,
+) -> Result<()> {
+    ensure!(
+        !cadence.is_empty(),
+        "There is no cadence to schedule posts with, set `schedule.cadence` in the config"
+    );
+
+    for slug in drafts {
+        match posts.iter().find(|p| &p.slug == slug) {
+            Some(post) if post.publish_date() == PublishDate::Draft => {}
+            Some(post) => bail!(
+                "The post '{slug}' is not a draft, it is published at {}",
+                post.published_at.as_deref().unwrap_or_default()
+            ),
+            None => bail!("There is no post with the slug '{slug}'"),
+        }
+    }
+
+    let taken = taken(posts);
+    let plan = drafts
+        .iter()
+        .zip(slots(cadence, &taken, today))
+        .collect::<Vec<_>>();
+
+    for (slug, date) in &plan {
+        println!("{} -> {slug}", date.format("%Y-%m-%d %a"));
+    }
+
+    for (slug, date) in &plan {
+        api::Update::builder()
+            .slug((*slug).clone())
+            .published_at(Some(PublishDate::Date(*date)))
+            .build()
+            .run(client, opts, |_| Ok(()))
+            .wrap_err_with(|| format!("Failed to schedule '{slug}'"))?;
+    }
+
+    if !opts.dry_run {
+        println!("Scheduled {} drafts", plan.len());
+    }
+
+    Ok(())
+}
+
+/// The days on which a post is published or scheduled.
+fn taken(posts: &[Post]) -> BTreeSet<Date> {
+    posts
+        .iter()
+        .filter_map(|p| match p.publish_date() {
+            PublishDate::Date(date) => Some(date),
+            PublishDate::Draft => None,
+        })
+        .collect()
+}
+
+/// The free days of the cadence after `today`.
+fn slots<'a>(
+    cadence: &'a [Weekday],
+    taken: &'a BTreeSet<Date>,
+    today: Date,
+) -> impl Iterator<Item = Date> + 'a {
+    today
+        .iter_days()
+        .skip(1)
+        .take_while(move |_| !cadence.is_empty())
+        .filter(move |d| cadence.contains(&d.weekday()) && !taken.contains(d))
+}
+
+#[test]
+fn free_slots() {
+    let date = |m, d| Date::from_ymd_opt(2015, m, d).unwrap();
+    let cadence = [Weekday::Tue, Weekday::Fri];
+    // Wednesday, with a post scheduled on the next Friday
+    let taken = [date(10, 23)].into();
+
+    let free = slots(&cadence, &taken, date(10, 21))
+        .take(3)
+        .collect::<Vec<_>>();
+    assert_eq!(free, [date(10, 27), date(10, 30), date(11, 3)]);
+}
+
+#[test]
+fn no_slots_without_cadence() {
+    let today = Date::from_ymd_opt(2015, 10, 21).unwrap();
+    assert_eq!(slots(&[], &BTreeSet::new(), today).next(), None);
+}
@@ -0,0 +1,380 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use kommandozeile::{
+    color_eyre::eyre::{bail, eyre, OptionExt as _, Report, WrapErr as _},
+    tracing::{debug, error, info, warn},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args::{self, SchedulerCommand},
+    config::Config,
+    dateformat::{self, PublishDate},
+};
+
+/// The longest time the scheduler sleeps before it reads the queue again,
+/// which also is the delay before a failed job is retried.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Jobs that are run later than this after their time are reported as missed.
+const MISSED_AFTER: Duration = Duration::from_secs(60);
+
+/// The formats that are accepted for the date and time of `--at`.
+const FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+];
+
+/// A draft that is published at a specific time.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Job {
+    id: u64,
+    slug: String,
+    #[serde(with = "rfc3339")]
+    at: DateTime<Utc>,
+    /// The time zone the job was scheduled in, which is used to show its time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    zone: Option<String>,
+}
+
+/// The jobs that are waiting to be run, as they are stored in the queue file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Queue {
+    #[serde(default)]
+    next_id: u64,
+    #[serde(default)]
+    jobs: Vec<Job>,
+}
+
+/// Runs the scheduler, or lists or cancels its jobs.
+pub fn run(client: &api::Client, opts: Opts, config: &Config, cmd: &args::Scheduler) -> Result<()> {
+    let path = queue_path(config)?;
+    match &cmd.cmd {
+        SchedulerCommand::Run(run) if run.due || opts.dry_run => {
+            let failed = run_due(client, opts, &path, config.timezone)?;
+            if failed > 0 {
+                bail!("{failed} jobs failed, they are retried on the next run");
+            }
+            Ok(())
+        }
+        SchedulerCommand::Run(_) => daemon(client, opts, &path, config.timezone),
+        SchedulerCommand::List(_) => list(&path),
+        SchedulerCommand::Cancel(cancel) => cancel_job(&path, &cancel.job),
+    }
+}
+
+/// Queues a job that publishes the draft `slug` at the time `at`.
+/// A job that already exists for the draft is replaced.
+pub fn add(client: &api::Client, opts: Opts, config: &Config, slug: &str, at: &str) -> Result<()> {
+    let (at, zone) = parse_at(at, config.timezone)?;
+
+    if opts.dry_run {
+        warn!("The post is not looked up with --dry-run, so it is not checked to be a draft");
+    } else {
+        let post = client.call(api::Get::builder().slug(slug.to_owned()).build())?;
+        if let PublishDate::Date(date) = post.publish_date() {
+            bail!("The post '{slug}' is not a draft, it is published at {date}");
+        }
+    }
+    if at <= Utc::now() {
+        warn!(
+            "The time has already passed, the post is published on the next run of the scheduler"
+        );
+    }
+
+    let path = queue_path(config)?;
+    let mut queue = Queue::load(&path)?;
+    let job = queue.add(slug, at, zone);
+    println!("Queued job {}: publish {slug} at {}", job.id, job.time());
+
+    if opts.dry_run {
+        return Ok(());
+    }
+    queue.save(&path)
+}
+
+/// Runs the jobs whose time has come, and sleeps until the next one is due,
+/// until the process is interrupted.
+fn daemon(client: &api::Client, opts: Opts, path: &Path, tz: Option<Tz>) -> Result<()> {
+    println!("Running the scheduler with the jobs in {}", path.display());
+
+    loop {
+        let failed = run_due(client, opts, path, tz)?;
+
+        let now = Utc::now();
+        let next = Queue::load(path)?.jobs.iter().map(|job| job.at).min();
+        let wait = match next {
+            Some(next) if failed == 0 => (next - now).to_std().unwrap_or_default(),
+            _ => POLL_INTERVAL,
+        };
+        let wait = wait.min(POLL_INTERVAL);
+        debug!(?next, ?wait, "Waiting for the next job");
+        thread::sleep(wait);
+    }
+}
+
+/// Runs every job that is due and removes it from the queue when it succeeds.
+/// Returns the number of jobs that failed.
+fn run_due(client: &api::Client, opts: Opts, path: &Path, tz: Option<Tz>) -> Result<usize> {
+    let now = Utc::now();
+    let mut due = Queue::load(path)?
+        .jobs
+        .into_iter()
+        .filter(|job| job.at <= now)
+        .collect::<Vec<_>>();
+    due.sort_by_key(|job| job.at);
+
+    let mut failed = 0;
+    for job in due {
+        if (now - job.at).to_std().unwrap_or_default() > MISSED_AFTER {
+            info!(
+                job = job.id,
+                slug = job.slug,
+                "Catching up on a missed job from {}",
+                job.time()
+            );
+        }
+
+        if let Err(err) = publish(client, opts, &job, tz) {
+            if !is_rejected(&err) {
+                error!(job = job.id, slug = job.slug, ?err, "Failed to publish");
+                failed += 1;
+                continue;
+            }
+            // retrying a request that mataroa rejects would fail forever
+            error!(
+                job = job.id,
+                slug = job.slug,
+                ?err,
+                "Failed to publish, dropping the job"
+            );
+        }
+        if opts.dry_run {
+            continue;
+        }
+
+        // read the queue again, in case it was changed in the meantime
+        let mut queue = Queue::load(path)?;
+        queue.jobs.retain(|j| j.id != job.id);
+        queue.save(path)?;
+    }
+
+    Ok(failed)
+}
+
+/// Whether `err` is a client error from the API, other than too many requests,
+/// such as the post no longer existing.
+fn is_rejected(err: &Report) -> bool {
+    matches!(
+        err.downcast_ref::<ureq::Error>(),
+        Some(ureq::Error::Status(status, _)) if (400..500).contains(status) && *status != 429
+    )
+}
+
+/// Publishes the draft of the job by setting its publish date to today.
+/// Posts that are no longer drafts are left as they are.
+fn publish(client: &api::Client, opts: Opts, job: &Job, tz: Option<Tz>) -> Result<()> {
+    let post = client.call(api::Get::builder().slug(job.slug.clone()).build())?;
+    if let PublishDate::Date(date) = post.publish_date() {
+        warn!(
+            job = job.id,
+            slug = job.slug,
+            "The post is already published at {date}, dropping the job"
+        );
+        return Ok(());
+    }
+
+    api::Update::builder()
+        .slug(job.slug.clone())
+        .published_at(Some(PublishDate::Date(dateformat::today(tz))))
+        .build()
+        .run(client, opts, |post| {
+            println!("Published {} (job {})", post.slug, job.id);
+            Ok(())
+        })
+}
+
+fn list(path: &Path) -> Result<()> {
+    let mut jobs = Queue::load(path)?.jobs;
+    if jobs.is_empty() {
+        println!("No scheduled jobs");
+        return Ok(());
+    }
+
+    jobs.sort_by_key(|job| job.at);
+    for job in jobs {
+        println!("{:>4}  {}  {}", job.id, job.time(), job.slug);
+    }
+    Ok(())
+}
+
+fn cancel_job(path: &Path, id_or_slug: &str) -> Result<()> {
+    let mut queue = Queue::load(path)?;
+    let Some(idx) = queue
+        .jobs
+        .iter()
+        .position(|job| job.id.to_string() == id_or_slug || job.slug == id_or_slug)
+    else {
+        bail!("There is no job '{id_or_slug}'");
+    };
+
+    let job = queue.jobs.remove(idx);
+    queue.save(path)?;
+    println!(
+        "Cancelled job {}: publish {} at {}",
+        job.id,
+        job.slug,
+        job.time()
+    );
+    Ok(())
+}
+
+/// Parses a date and time with an optional time zone name at the end,
+/// e.g. `2026-11-02 09:00 Europe/Berlin`.
+/// Without a time zone, the time zone of the blog is used.
+fn parse_at(value: &str, tz: Option<Tz>) -> Result<(DateTime<Utc>, Option<Tz>)> {
+    let value = value.trim();
+    let (datetime, zone) = match value.rsplit_once(char::is_whitespace) {
+        Some((datetime, zone)) => zone
+            .parse::<Tz>()
+            .map_or((value, None), |zone| (datetime.trim_end(), Some(zone))),
+        None => (value, None),
+    };
+    let zone = zone.or(tz);
+
+    let naive = FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(datetime, format).ok())
+        .ok_or_else(|| {
+            eyre!("Invalid date and time '{datetime}', expected e.g. \"2026-11-02 09:00 Europe/Berlin\"")
+        })?;
+
+    let at = zone.map_or_else(
+        || {
+            Local
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|at| at.with_timezone(&Utc))
+        },
+        |zone| {
+            zone.from_local_datetime(&naive)
+                .earliest()
+                .map(|at| at.with_timezone(&Utc))
+        },
+    );
+    let at = at.ok_or_eyre(format!("'{datetime}' doesn't exist in the time zone"))?;
+    Ok((at, zone))
+}
+
+/// The queue file from the config, or the default one in the data directory.
+fn queue_path(config: &Config) -> Result<PathBuf> {
+    if let Some(path) = &config.scheduler.queue {
+        return Ok(path.clone());
+    }
+    let dir = dirs::data_dir().ok_or_eyre("There is no data directory for the scheduler queue")?;
+    Ok(dir.join("mataroa").join("jobs.json"))
+}
+
+impl Job {
+    /// The time of the job in the time zone it was scheduled in.
+    fn time(&self) -> String {
+        const FORMAT: &str = "%Y-%m-%d %H:%M %Z";
+        self.zone
+            .as_deref()
+            .and_then(|zone| zone.parse::<Tz>().ok())
+            .map_or_else(
+                || self.at.with_timezone(&Local).format(FORMAT).to_string(),
+                |zone| self.at.with_timezone(&zone).format(FORMAT).to_string(),
+            )
+    }
+}
+
+impl Queue {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let queue = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read the scheduler queue '{}'", path.display()))?;
+        serde_json::from_str(&queue)
+            .wrap_err_with(|| format!("Invalid scheduler queue '{}'", path.display()))
+    }
+
+    /// Writes the queue to a temporary file first, so that it is never left
+    /// half-written.
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        let mut queue = serde_json::to_string_pretty(self)?;
+        queue.push('\n');
+        fs::write(&tmp, queue)
+            .and_then(|()| fs::rename(&tmp, path))
+            .wrap_err_with(|| format!("Failed to write the scheduler queue '{}'", path.display()))
+    }
+
+    fn add(&mut self, slug: &str, at: DateTime<Utc>, zone: Option<Tz>) -> &Job {
+        self.jobs.retain(|job| job.slug != slug);
+        self.next_id += 1;
+        self.jobs.push(Job {
+            id: self.next_id,
+            slug: slug.to_owned(),
+            at,
+            zone: zone.map(|zone| zone.name().to_owned()),
+        });
+        &self.jobs[self.jobs.len() - 1]
+    }
+}
+
+mod rfc3339 {
+    use chrono::{DateTime, Utc};
+    use serde::{de, Deserialize as _, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(at: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&at.to_rfc3339())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        let at = String::deserialize(deserializer)?;
+        DateTime::parse_from_rfc3339(&at)
+            .map(|at| at.with_timezone(&Utc))
+            .map_err(de::Error::custom)
+    }
+}
+
+#[test]
+fn queue_jobs() {
+    let (at, zone) = parse_at("2026-11-02 09:00 Europe/Berlin", None).unwrap();
+    assert_eq!(at.to_rfc3339(), "2026-11-02T08:00:00+00:00");
+    assert_eq!(zone, Some(Tz::Europe__Berlin));
+    let (at, _) = parse_at("2026-07-01T09:00:30 Europe/Berlin", None).unwrap();
+    assert_eq!(at.to_rfc3339(), "2026-07-01T07:00:30+00:00");
+    assert!(parse_at("tomorrow Europe/Berlin", None).is_err());
+    // skipped by the change to summer time
+    assert!(parse_at("2026-03-29 02:30 Europe/Berlin", None).is_err());
+
+    let mut queue = Queue::default();
+    let _ = queue.add("a", at, None);
+    let _ = queue.add("b", at, Some(Tz::UTC));
+    let job = queue.add("a", at, Some(Tz::Europe__Berlin)).clone();
+    assert_eq!(job.id, 3);
+    assert_eq!(job.time(), "2026-07-01 09:00 CEST");
+    assert_eq!(queue.jobs.len(), 2);
+
+    let json = serde_json::to_string(&queue).unwrap();
+    assert_eq!(serde_json::from_str::<Queue>(&json).unwrap(), queue);
+}
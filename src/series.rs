@@ -0,0 +1,558 @@
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use kommandozeile::{
+    color_eyre::eyre::{bail, OptionExt as _},
+    tracing::warn,
+    Result,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args::{self, SeriesCommand},
+    blocks,
+    config::Config,
+    dateformat::{self, Offset, PublishDate},
+    transform, Post, PostInput,
+};
+
+/// The name of the data that records the series in the body of a post.
+const DATA: &str = "series";
+/// The name of the "Part N of M" block at the start of a part.
+const HEADER: &str = "series-header";
+/// The name of the block with the links to the previous and next part.
+const NAV: &str = "series-nav";
+
+/// The series a post belongs to, as given in the frontmatter.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Series {
+    pub name: String,
+    /// The position of the post in the series. Parts are sorted by it,
+    /// so it doesn't have to start at 1 or be without gaps.
+    pub order: u32,
+}
+
+/// A post of a series.
+#[derive(Clone, Debug)]
+struct Part<'a> {
+    post: &'a Post,
+    series: Series,
+}
+
+impl Series {
+    /// The series that `body` belongs to, if any.
+    fn of(body: &str) -> Option<Self> {
+        serde_json::from_str(blocks::data(body, DATA)?).ok()
+    }
+
+    /// Records in `body` that it belongs to this series.
+    fn write_to(&self, body: &str) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        blocks::set_data(body, DATA, &json)
+    }
+}
+
+/// Records the series of the post in its body.
+pub fn apply_to(post: &mut PostInput) {
+    if let (Some(series), Some(body)) = (&post.series, &post.body) {
+        post.body = Some(series.write_to(body));
+    }
+}
+
+/// Keeps the order and the generated header and navigation of the part
+/// `slug` as they are on the server, when it is updated from its post file.
+///
+/// They are managed by `series reorder` and `series nav`, which only change
+/// the posts on the server, so the post file has an outdated order and no
+/// header and navigation.
+/// Nothing is kept if the post file moves the part to another series.
+pub fn keep_on_update(
+    client: &api::Client,
+    opts: Opts,
+    post: &mut PostInput,
+    slug: &str,
+) -> Result<()> {
+    if post.series.is_none() || post.body.is_none() {
+        return Ok(());
+    }
+    if opts.dry_run {
+        warn!("The order and navigation of the series on the server are not kept in a dry run");
+        return Ok(());
+    }
+
+    let current = client.call(api::Get::builder().slug(slug.to_owned()).build())?;
+    if let Some(current) = current.body.as_deref() {
+        keep(post, current);
+    }
+    Ok(())
+}
+
+/// Takes the order, the header, and the navigation of `post` from `current`,
+/// the body of the part on the server, if that is a part of the same series.
+fn keep(post: &mut PostInput, current: &str) {
+    let (Some(series), Some(body)) = (&mut post.series, &post.body) else {
+        return;
+    };
+    let Some(order) = Series::of(current)
+        .filter(|s| s.name == series.name)
+        .map(|s| s.order)
+    else {
+        return;
+    };
+
+    series.order = order;
+    let mut body = series.write_to(body);
+    if let Some(header) = blocks::content(current, HEADER) {
+        body = blocks::replace_at_start(&body, HEADER, header);
+    }
+    if let Some(nav) = blocks::content(current, NAV) {
+        body = blocks::replace(&body, NAV, nav);
+    }
+    post.body = Some(body);
+}
+
+/// Lists, reorders, or schedules the parts of series, or refreshes their
+/// navigation.
+pub fn run(client: &api::Client, opts: Opts, config: &Config, cmd: &args::Series) -> Result<()> {
+    let posts = client.all_posts()?;
+    let all = collect(&posts);
+    let parts = |name: &str| {
+        all.get(name)
+            .map(Vec::as_slice)
+            .ok_or_eyre(format!("There is no series '{name}'"))
+    };
+
+    match &cmd.cmd {
+        SeriesCommand::List(list) => {
+            match &list.name {
+                Some(name) => print_parts(parts(name)?),
+                None if all.is_empty() => println!("There are no series"),
+                None => {
+                    for (name, parts) in &all {
+                        println!("{name} ({} parts)", parts.len());
+                    }
+                }
+            }
+            Ok(())
+        }
+        SeriesCommand::Reorder(reorder) => {
+            let parts = reorder_parts(parts(&reorder.name)?, &reorder.slugs)?;
+            print_parts(&parts);
+            update(client, opts, &parts)
+        }
+        SeriesCommand::Schedule(schedule) => {
+            let start = schedule
+                .start
+                .as_deref()
+                .map(|start| crate::parse_date(client, config, start))
+                .transpose()?;
+            let plan = plan_schedule(
+                parts(&schedule.name)?,
+                schedule.every,
+                start,
+                dateformat::today(config.timezone),
+            )?;
+            schedule_parts(client, opts, &plan)
+        }
+        SeriesCommand::Nav(nav) => match &nav.name {
+            Some(name) => update(client, opts, parts(name)?),
+            None => all
+                .values()
+                .try_for_each(|parts| update(client, opts, parts)),
+        },
+    }
+}
+
+/// The slugs of the parts of the series `name`, in order.
+pub fn slugs(posts: &[Post], name: &str) -> Result<Vec<String>> {
+    let all = collect(posts);
+    let parts = all
+        .get(name)
+        .ok_or_eyre(format!("There is no series '{name}'"))?;
+    Ok(parts.iter().map(|part| part.post.slug.clone()).collect())
+}
+
+/// The parts of every series, by the name of the series, in order.
+fn collect(posts: &[Post]) -> BTreeMap<String, Vec<Part<'_>>> {
+    let mut all = BTreeMap::<_, Vec<_>>::new();
+    for post in posts {
+        if let Some(series) = post.body.as_deref().and_then(Series::of) {
+            all.entry(series.name.clone())
+                .or_default()
+                .push(Part { post, series });
+        }
+    }
+    for parts in all.values_mut() {
+        parts.sort_by(|a, b| (a.series.order, &a.post.slug).cmp(&(b.series.order, &b.post.slug)));
+    }
+    all
+}
+
+fn print_parts(parts: &[Part<'_>]) {
+    for (n, part) in parts.iter().enumerate() {
+        let title = part.post.title.as_deref().unwrap_or_default();
+        let date = part.post.published_at.as_deref().unwrap_or_default();
+        let date = if date.is_empty() { "draft" } else { date };
+        println!("{:>3}. {}: {title} [{date}]", n + 1, part.post.slug);
+    }
+}
+
+/// Moves the parts with the given slugs to the front, in the given order,
+/// and numbers all parts from 1.
+fn reorder_parts<'a>(parts: &[Part<'a>], slugs: &[String]) -> Result<Vec<Part<'a>>> {
+    let mut reordered = Vec::with_capacity(parts.len());
+    for slug in slugs {
+        let Some(part) = parts.iter().find(|p| &p.post.slug == slug) else {
+            bail!("The post '{slug}' is not a part of the series");
+        };
+        if reordered.iter().any(|p: &Part<'_>| &p.post.slug == slug) {
+            bail!("The post '{slug}' is given more than once");
+        }
+        reordered.push(part.clone());
+    }
+    for part in parts {
+        if !slugs.contains(&part.post.slug) {
+            reordered.push(part.clone());
+        }
+    }
+
+    for (order, part) in (1..).zip(&mut reordered) {
+        part.series.order = order;
+    }
+    Ok(reordered)
+}
+
+/// The dates for the parts that are drafts, one interval apart.
+fn plan_schedule<'a>(
+    parts: &[Part<'a>],
+    every: Offset,
+    start: Option<PublishDate>,
+    today: chrono::NaiveDate,
+) -> Result<Vec<(&'a Post, chrono::NaiveDate)>> {
+    let start = match start {
+        Some(PublishDate::Date(start)) => start,
+        Some(PublishDate::Draft) => bail!("The start of the schedule must be a date"),
+        None => {
+            let last = parts
+                .iter()
+                .filter_map(|p| match p.post.publish_date() {
+                    PublishDate::Date(date) => Some(date),
+                    PublishDate::Draft => None,
+                })
+                .max()
+                .unwrap_or(today);
+            every
+                .add_to(last)
+                .ok_or_eyre("The schedule is out of range")?
+        }
+    };
+
+    parts
+        .iter()
+        .filter(|p| p.post.publish_date() == PublishDate::Draft)
+        .zip(0..)
+        .map(|(part, n)| {
+            let date = every
+                .times(n)
+                .and_then(|offset| offset.add_to(start))
+                .ok_or_eyre("The schedule is out of range")?;
+            Ok((part.post, date))
+        })
+        .collect()
+}
+
+fn schedule_parts(
+    client: &api::Client,
+    opts: Opts,
+    plan: &[(&Post, chrono::NaiveDate)],
+) -> Result<()> {
+    if plan.is_empty() {
+        println!("All parts already have a date");
+        return Ok(());
+    }
+
+    for (post, date) in plan {
+        println!("{} -> {}", date.format("%Y-%m-%d %a"), post.slug);
+    }
+
+    for (post, date) in plan {
+        api::Update::builder()
+            .slug(post.slug.clone())
+            .published_at(Some(PublishDate::Date(*date)))
+            .build()
+            .run(client, opts, |_| Ok(()))?;
+    }
+
+    if !opts.dry_run {
+        println!("Scheduled {} parts", plan.len());
+    }
+    Ok(())
+}
+
+/// Writes the order, the header, and the navigation into every part of the
+/// series. Only parts whose body actually changes are updated.
+fn update(client: &api::Client, opts: Opts, parts: &[Part<'_>]) -> Result<()> {
+    let links = parts.iter().map(|part| link(part.post)).collect::<Vec<_>>();
+    let mut updated = 0_usize;
+
+    for (idx, part) in parts.iter().enumerate() {
+        let Some(body) = part.post.body.as_deref() else {
+            continue;
+        };
+
+        let new_body = part.series.write_to(body);
+        let new_body = navigate(&new_body, &part.series.name, &links, idx);
+        if new_body == body {
+            continue;
+        }
+
+        api::Update::builder()
+            .slug(part.post.slug.clone())
+            .body(Some(new_body))
+            .build()
+            .run(client, opts, |_| {
+                println!("Updated {}", part.post.slug);
+                Ok(())
+            })?;
+        updated += 1;
+    }
+
+    if !opts.dry_run {
+        let name = parts.first().map_or("", |p| p.series.name.as_str());
+        println!("Updated {updated} parts of {name}");
+    }
+    Ok(())
+}
+
+/// The title and the URL of a post, to link to it.
+fn link(post: &Post) -> (String, String) {
+    let title = post.title.as_deref().unwrap_or(&post.slug);
+    let url = post
+        .url
+        .clone()
+        .unwrap_or_else(|| format!("/blog/{}/", post.slug));
+    (title.to_owned(), url)
+}
+
+/// Adds or refreshes the header and the navigation in the body of the part
+/// at `idx` of the series `name`, whose parts have the given titles and URLs.
+pub fn navigate(body: &str, name: &str, links: &[(String, String)], idx: usize) -> String {
+    let body = blocks::replace_at_start(body, HEADER, &header(name, idx, links.len()));
+    blocks::replace(&body, NAV, &nav(links, idx))
+}
+
+/// The "Part N of M" header of the part at `idx`.
+fn header(name: &str, idx: usize, count: usize) -> String {
+    format!(
+        "*Part {} of {count} of the series \"{}\"*",
+        idx + 1,
+        transform::link_text(name)
+    )
+}
+
+/// The links to the previous and next part of the part at `idx`.
+fn nav(links: &[(String, String)], idx: usize) -> String {
+    let mut nav = String::new();
+    if let Some((title, url)) = idx.checked_sub(1).map(|prev| &links[prev]) {
+        let _ = write!(nav, "[← Previous: {}]({url})", transform::link_text(title));
+    }
+    if let Some((title, url)) = links.get(idx + 1) {
+        if !nav.is_empty() {
+            nav.push_str(" | ");
+        }
+        let _ = write!(nav, "[Next: {} →]({url})", transform::link_text(title));
+    }
+    nav
+}
+
+#[test]
+fn collect_parts() {
+    let part = |slug: &str, order, published_at: &str| Post {
+        slug: slug.to_owned(),
+        title: Some(slug.to_uppercase()),
+        body: Some(
+            Series {
+                name: String::from("Rust"),
+                order,
+            }
+            .write_to("Body"),
+        ),
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let posts = [
+        part("c", 3, ""),
+        part("a", 1, "2015-10-21"),
+        part("b", 2, ""),
+        Post {
+            body: Some(String::from("Not in a series")),
+            ..part("x", 1, "")
+        },
+    ];
+
+    let all = collect(&posts);
+    assert_eq!(all.len(), 1);
+    assert_eq!(
+        all["Rust"]
+            .iter()
+            .map(|p| p.post.slug.as_str())
+            .collect::<Vec<_>>(),
+        ["a", "b", "c"]
+    );
+}
+
+#[test]
+fn reorder_series() {
+    let part = |slug: &str, order, published_at: &str| Post {
+        slug: slug.to_owned(),
+        title: Some(slug.to_uppercase()),
+        body: Some(
+            Series {
+                name: String::from("Rust"),
+                order,
+            }
+            .write_to("Body"),
+        ),
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let posts = [part("a", 1, ""), part("b", 2, ""), part("c", 3, "")];
+    let all = collect(&posts);
+
+    let parts = reorder_parts(&all["Rust"], &[String::from("c")]).unwrap();
+    assert_eq!(
+        parts
+            .iter()
+            .map(|p| p.post.slug.as_str())
+            .collect::<Vec<_>>(),
+        ["c", "a", "b"]
+    );
+    assert_eq!(parts[2].series.order, 3);
+    assert!(reorder_parts(&all["Rust"], &[String::from("x")]).is_err());
+    assert!(reorder_parts(&all["Rust"], &[String::from("a"), String::from("a")]).is_err());
+}
+
+#[test]
+fn navigation() {
+    let part = |slug: &str, order, published_at: &str| Post {
+        slug: slug.to_owned(),
+        title: Some(slug.to_uppercase()),
+        body: Some(
+            Series {
+                name: String::from("Rust"),
+                order,
+            }
+            .write_to("Body"),
+        ),
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let posts = [part("c", 1, ""), part("a", 2, ""), part("b", 3, "")];
+    let links = posts.iter().map(link).collect::<Vec<_>>();
+
+    assert_eq!(header("Rust", 0, 3), "*Part 1 of 3 of the series \"Rust\"*");
+    assert_eq!(
+        header("*nix_tools", 1, 3),
+        "*Part 2 of 3 of the series \"\\*nix\\_tools\"*"
+    );
+    assert_eq!(nav(&links, 0), "[Next: A →](/blog/a/)");
+    assert_eq!(
+        nav(&links, 1),
+        "[← Previous: C](/blog/c/) | [Next: B →](/blog/b/)"
+    );
+    assert_eq!(nav(&links, 2), "[← Previous: A](/blog/a/)");
+}
+
+#[test]
+fn navigation_escapes_titles() {
+    let links = [
+        (String::from("[Draft] One"), String::from("/blog/one/")),
+        (String::from("Two"), String::from("/blog/two/")),
+    ];
+    assert_eq!(nav(&links, 1), "[← Previous: \\[Draft\\] One](/blog/one/)");
+}
+
+#[test]
+fn schedule_drafts() {
+    let part = |slug: &str, order, published_at: &str| Post {
+        slug: slug.to_owned(),
+        title: Some(slug.to_uppercase()),
+        body: Some(
+            Series {
+                name: String::from("Rust"),
+                order,
+            }
+            .write_to("Body"),
+        ),
+        published_at: Some(published_at.to_owned()),
+        url: None,
+    };
+    let date = |d| chrono::NaiveDate::from_ymd_opt(2015, 10, d).unwrap();
+    let posts = [
+        part("a", 1, "2015-10-21"),
+        part("b", 2, ""),
+        part("c", 3, ""),
+    ];
+    let all = collect(&posts);
+    let every = "1w".parse().unwrap();
+
+    let plan = plan_schedule(&all["Rust"], every, None, date(1)).unwrap();
+    let plan = plan
+        .iter()
+        .map(|(p, d)| (p.slug.as_str(), *d))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        plan,
+        [("b", date(28)), ("c", date(28) + chrono::Days::new(7))]
+    );
+
+    let start = Some(PublishDate::Date(date(2)));
+    let plan = plan_schedule(&all["Rust"], every, start, date(1)).unwrap();
+    assert_eq!(plan[0].1, date(2));
+    assert!(plan_schedule(&all["Rust"], every, Some(PublishDate::Draft), date(1)).is_err());
+}
+
+#[test]
+fn keep_series_on_update() {
+    let series = |order| Series {
+        name: String::from("Rust"),
+        order,
+    };
+    let current = navigate(
+        &series(1).write_to("Old"),
+        "Rust",
+        &[
+            (String::from("One"), String::from("/blog/one/")),
+            (String::from("Two"), String::from("/blog/two/")),
+        ],
+        0,
+    );
+    let mut post = PostInput {
+        series: Some(series(2)),
+        body: Some(series(2).write_to("New")),
+        ..PostInput::default()
+    };
+
+    keep(&mut post, &current);
+    assert_eq!(post.series, Some(series(1)));
+    assert_eq!(
+        post.body.as_deref(),
+        Some(concat!(
+            "<!-- roa:series-header -->\n*Part 1 of 2 of the series \"Rust\"*\n<!-- /roa:series-header -->\n\n",
+            "New\n\n<!-- roa:series {\"name\":\"Rust\",\"order\":1} -->\n\n",
+            "<!-- roa:series-nav -->\n[Next: Two →](/blog/two/)\n<!-- /roa:series-nav -->",
+        ))
+    );
+
+    let mut moved = PostInput {
+        series: Some(Series {
+            name: String::from("Go"),
+            order: 2,
+        }),
+        body: Some(String::from("Moved")),
+        ..PostInput::default()
+    };
+    keep(&mut moved, &current);
+    assert_eq!(moved.body.as_deref(), Some("Moved"));
+}
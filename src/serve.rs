@@ -0,0 +1,276 @@
+use std::{
+    fmt::Write as _,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    thread,
+    time::Duration,
+};
+
+use kommandozeile::{
+    color_eyre::eyre::eyre,
+    tracing::{info, warn},
+    InputFile, Result,
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::{
+    config::Config,
+    preview::{self, Page},
+    watcher::{is_post_file, Watcher},
+    PostInput,
+};
+
+/// How often the watched files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a browser waits for a change before asking again.
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many requests are handled at the same time.
+///
+/// Every open page keeps one request waiting for changes, so this is the
+/// number of pages that can be open before new requests have to wait.
+const WORKERS: usize = 16;
+
+const CHANGES_PATH: &str = "/__roa/changes";
+
+/// Characters that need to be encoded in the path of a link to a file.
+const PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Long-polls the server and reloads the page once any watched file changes.
+const RELOAD_SCRIPT: &str = r"
+(async function () {
+  for (;;) {
+    try {
+      const res = await fetch('/__roa/changes?since=' + version);
+      const current = parseInt(await res.text(), 10);
+      if (current !== version) {
+        location.reload();
+        return;
+      }
+    } catch (e) {
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+    }
+  }
+})();
+";
+
+/// Serves rendered previews of the post files in `root` on `addr`.
+///
+/// `root` can be a single post file or a directory of post files.
+/// Every request renders the file fresh from disk, and a background thread
+/// watches the files so that open pages reload whenever anything changes.
+/// Requests are handled by a fixed number of threads.
+pub fn run(root: &Path, addr: &str, config: &Config) -> Result<()> {
+    let watcher = Watcher::new(root)?;
+    let server = Server::http(addr).map_err(|e| eyre!(e))?;
+
+    println!(
+        "Serving previews of {} on http://{}/",
+        root.display(),
+        server.server_addr()
+    );
+
+    let state = Arc::new(State {
+        watcher: Mutex::new(watcher),
+        version: Mutex::new(0),
+        changed: Condvar::new(),
+        config: config.clone(),
+    });
+
+    let watch_state = Arc::clone(&state);
+    let _watch = thread::spawn(move || watch_state.watch());
+
+    let server = Arc::new(server);
+    let workers = (0..WORKERS)
+        .map(|_| {
+            let (server, state) = (Arc::clone(&server), Arc::clone(&state));
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    if let Err(err) = state.handle(request) {
+                        warn!(?err, "Failed to respond to request");
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|_| eyre!("A request handler panicked"))?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct State {
+    watcher: Mutex<Watcher>,
+    version: Mutex<u64>,
+    changed: Condvar,
+    config: Config,
+}
+
+impl State {
+    fn watch(&self) {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let changes = self
+                .watcher
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .poll();
+
+            match changes {
+                Ok(changes) if changes.is_empty() => {}
+                Ok(changes) => {
+                    for change in &changes {
+                        info!(path = %change.path().display(), "Reloading");
+                    }
+                    *self.version.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+                    self.changed.notify_all();
+                }
+                Err(err) => warn!(?err, "Failed to check for changes"),
+            }
+        }
+    }
+
+    fn version(&self) -> u64 {
+        *self.version.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait_for_change(&self, since: u64) -> u64 {
+        let version = self.version.lock().unwrap_or_else(PoisonError::into_inner);
+        let (version, _) = self
+            .changed
+            .wait_timeout_while(version, LONG_POLL_TIMEOUT, |v| *v <= since)
+            .unwrap_or_else(PoisonError::into_inner);
+        *version
+    }
+
+    fn handle(&self, request: Request) -> Result<()> {
+        let url = request.url().to_owned();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        if path == CHANGES_PATH {
+            let since = query
+                .split('&')
+                .find_map(|kv| kv.strip_prefix("since="))
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default();
+            let version = self.wait_for_change(since);
+            request.respond(Response::from_string(version.to_string()))?;
+            return Ok(());
+        }
+
+        let version = self.version();
+        let response = match self.resolve(path) {
+            Some(file) => html(render_file(&file, version, &self.config)),
+            None if path == "/" => html(self.render_index(version)),
+            None => html(not_found(path, version)).with_status_code(404),
+        };
+
+        request.respond(response)?;
+        Ok(())
+    }
+
+    /// The watched root and all files that are currently known.
+    fn files(&self) -> (PathBuf, Vec<PathBuf>) {
+        let watcher = self.watcher.lock().unwrap_or_else(PoisonError::into_inner);
+        let files = watcher.files().map(Path::to_path_buf).collect();
+        (watcher.root().to_path_buf(), files)
+    }
+
+    /// Finds the watched file that is requested by `path`.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let (root, files) = self.files();
+
+        if !root.is_dir() {
+            return (path == "/").then_some(root);
+        }
+
+        let path = percent_decode_str(path.trim_start_matches('/'))
+            .decode_utf8()
+            .ok()?;
+        let file = root.join(path.as_ref());
+        files
+            .iter()
+            .any(|f| *f == file && is_post_file(f))
+            .then_some(file)
+    }
+
+    fn render_index(&self, version: u64) -> String {
+        let (root, files) = self.files();
+
+        let mut content = String::from("<article>\n<header>\n<h1>Posts</h1>\n</header>\n<ul>\n");
+        for file in &files {
+            let name = file.strip_prefix(&root).unwrap_or(file).to_string_lossy();
+            let href = utf8_percent_encode(&name, PATH);
+            let _ = write!(content, "<li><a href=\"/{href}\">");
+            preview::push_escaped(&mut content, &name);
+            content.push_str("</a></li>\n");
+        }
+        content.push_str("</ul>\n</article>\n");
+
+        preview::document("Posts", &content, Some(&reload_script(version)))
+    }
+}
+
+fn render_file(file: &Path, version: u64, config: &Config) -> String {
+    let script = reload_script(version);
+    let post = PostInput::from_file(&InputFile::File(file.to_path_buf()), config.timezone)
+        .and_then(|mut post| {
+            post.render(config)?;
+            Ok(post)
+        });
+    match post {
+        Ok(post) => {
+            let mut content = String::new();
+            preview::render_article(&mut content, Page::from(&post));
+            preview::document(
+                post.title.as_deref().unwrap_or("Untitled"),
+                &content,
+                Some(&script),
+            )
+        }
+        Err(err) => {
+            let mut content = String::from("<article>\n<pre class=\"error\">");
+            preview::push_escaped(
+                &mut content,
+                &format!("Failed to render {}:\n\n{err:#}", file.display()),
+            );
+            content.push_str("</pre>\n</article>\n");
+            preview::document("Error", &content, Some(&script))
+        }
+    }
+}
+
+fn not_found(path: &str, version: u64) -> String {
+    let mut content = String::from("<article>\n<pre class=\"error\">Not found: ");
+    preview::push_escaped(&mut content, path);
+    content.push_str("</pre>\n<p><a href=\"/\">All posts</a></p>\n</article>\n");
+    preview::document("Not found", &content, Some(&reload_script(version)))
+}
+
+fn reload_script(version: u64) -> String {
+    format!("const version = {version};{RELOAD_SCRIPT}")
+}
+
+fn html(content: String) -> Response<Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", "text/html; charset=utf-8")
+        .expect("static header is valid");
+    Response::from_string(content).with_header(header)
+}
@@ -0,0 +1,166 @@
+use kommandozeile::{color_eyre::eyre::bail, tracing::warn, Result};
+use unicode_normalization::UnicodeNormalization as _;
+
+use crate::{
+    api::{self, Opts},
+    Post,
+};
+
+/// Turns a title into a slug, the same way that mataroa does.
+///
+/// mataroa uses the `slugify` function of Django: The text is normalized to
+/// ASCII, everything that is not alphanumeric, an underscore, a hyphen, or
+/// whitespace is dropped, and runs of whitespace and hyphens are replaced by a
+/// single hyphen.
+/// mataroa additionally truncates the slug to 100 characters.
+///
+/// If the resulting slug is empty (e.g. the title only consists of emoji),
+/// mataroa generates a random one instead, which cannot be predicted.
+pub fn slugify(title: &str) -> String {
+    let ascii = title
+        .nfkd()
+        .filter(char::is_ascii)
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-') || c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    let mut slug = String::with_capacity(ascii.len());
+    for part in ascii.split(|c: char| c == '-' || c.is_whitespace()) {
+        if part.is_empty() {
+            continue;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(part);
+    }
+
+    let slug = slug.trim_matches(|c| c == '-' || c == '_');
+    slug.chars().take(100).collect()
+}
+
+/// How a predicted slug relates to the existing posts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Prediction {
+    /// The slug is free and will be used as is.
+    Free(String),
+    /// The slug is taken by another post, mataroa will append a random suffix.
+    Taken(String, Post),
+    /// The slug cannot be predicted, mataroa will generate a random one.
+    Random,
+}
+
+/// Predicts the slug of `title` and checks it against the existing `posts`.
+pub fn predict(title: &str, posts: Vec<Post>) -> Prediction {
+    check(&slugify(title), None, posts)
+}
+
+/// Checks `slug` as it is against the existing `posts`.
+/// The post with the slug `current` is the one being renamed and not counted
+/// as a collision.
+pub fn check(slug: &str, current: Option<&str>, posts: Vec<Post>) -> Prediction {
+    if slug.is_empty() {
+        return Prediction::Random;
+    }
+
+    let taken = posts
+        .into_iter()
+        .find(|p| p.slug == slug && Some(p.slug.as_str()) != current);
+    let slug = slug.to_owned();
+    match taken {
+        Some(post) => Prediction::Taken(slug, post),
+        None => Prediction::Free(slug),
+    }
+}
+
+/// Checks that the slug for `title` does not clash with an existing post
+/// before creating a post.
+///
+/// A clash is reported as a warning, or as an error if `abort` is set.
+/// Nothing is checked during a dry run, as that would require a request.
+pub fn preflight(client: &api::Client, opts: Opts, title: &str, abort: bool) -> Result<()> {
+    if opts.dry_run {
+        return Ok(());
+    }
+    let posts = client.call(api::List {})?;
+    report(predict(title, posts), title, abort)
+}
+
+/// Checks that `slug`, exactly as it is sent to mataroa, does not clash with
+/// another post before the post with the slug `current` is renamed to it.
+///
+/// Like [`preflight`], but the slug is not derived from a title.
+pub fn preflight_rename(
+    client: &api::Client,
+    opts: Opts,
+    slug: &str,
+    current: &str,
+    abort: bool,
+) -> Result<()> {
+    if opts.dry_run {
+        return Ok(());
+    }
+    let posts = client.call(api::List {})?;
+    report(check(slug, Some(current), posts), slug, abort)
+}
+
+fn report(prediction: Prediction, title: &str, abort: bool) -> Result<()> {
+    match prediction {
+        Prediction::Free(_) => Ok(()),
+        Prediction::Random => {
+            warn!(
+                title,
+                "Cannot predict the slug, mataroa will generate a random one"
+            );
+            Ok(())
+        }
+        Prediction::Taken(slug, post) => {
+            let title = post.title.as_deref().unwrap_or_default();
+            if abort {
+                bail!("The slug '{slug}' is already used by the post '{title}'");
+            }
+            warn!(
+                slug,
+                post = title,
+                "The slug is already used by another post, mataroa will add a random suffix to it"
+            );
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn slugify_like_django() {
+    assert_eq!(slugify("My first post"), "my-first-post");
+    assert_eq!(slugify("  Hello,   World! "), "hello-world");
+    assert_eq!(slugify("Crème brûlée — a recipe"), "creme-brulee-a-recipe");
+    assert_eq!(slugify("snake_case and -- dashes"), "snake_case-and-dashes");
+    assert_eq!(slugify("🎉"), "");
+}
+
+#[test]
+fn check_literal_slugs() {
+    let post = |slug: &str| Post {
+        slug: slug.to_owned(),
+        title: None,
+        body: None,
+        published_at: None,
+        url: None,
+    };
+    let posts = vec![post("my-post"), post("My_Post")];
+    assert!(
+        matches!(predict("My Post", posts.clone()), Prediction::Taken(slug, _) if slug == "my-post")
+    );
+    assert_eq!(
+        check("My Post", None, posts.clone()),
+        Prediction::Free(String::from("My Post"))
+    );
+    assert!(matches!(
+        check("My_Post", None, posts.clone()),
+        Prediction::Taken(..)
+    ));
+    assert_eq!(
+        check("My_Post", Some("My_Post"), posts),
+        Prediction::Free(String::from("My_Post"))
+    );
+}
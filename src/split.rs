@@ -0,0 +1,262 @@
+use std::{fs, path::PathBuf};
+
+use kommandozeile::{
+    color_eyre::eyre::{bail, ensure, OptionExt as _, WrapErr as _},
+    Result,
+};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+
+use crate::{
+    api::{self, Opts},
+    args::{self, Heading},
+    config::Config,
+    dateformat::{self, PublishDate},
+    frontmatter, series, slug, PostInput,
+};
+
+/// A part of a split post file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Part {
+    title: String,
+    slug: String,
+    published_at: PublishDate,
+    body: String,
+}
+
+/// Splits a post file at its headings and writes the parts as new post files.
+pub fn run(client: &api::Client, opts: Opts, config: &Config, cmd: &args::Split) -> Result<()> {
+    let path = &cmd.file;
+    let content = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read '{}'", path.display()))?;
+    let mut post = PostInput::parse(&content, config.timezone)?;
+    if cmd.every.is_some() && cmd.start.is_none() {
+        crate::resolve_date(client, config, &mut post)?;
+    }
+    let title = post
+        .title
+        .as_deref()
+        .ok_or_eyre("The post has no title to name the parts after")?;
+
+    let body = content[frontmatter::body_start(&content)..].trim();
+    let at = level(cmd.at);
+    let sections = sections(body, at);
+    ensure!(
+        sections.len() > 1,
+        "The post has no second heading of level {at} or higher to split at"
+    );
+
+    let dates = match cmd.every {
+        None => vec![PublishDate::Draft; sections.len()],
+        Some(every) => {
+            let start = cmd
+                .start
+                .as_deref()
+                .map(|start| crate::parse_date(client, config, start))
+                .transpose()?;
+            let start = match start.or(post.published_at) {
+                Some(PublishDate::Date(start)) => start,
+                Some(PublishDate::Draft) if cmd.start.is_some() => {
+                    bail!("The start of the schedule must be a date")
+                }
+                _ => every
+                    .add_to(dateformat::today(config.timezone))
+                    .ok_or_eyre("The schedule is out of range")?,
+            };
+            (0..sections.len())
+                .map(|n| {
+                    let n = i64::try_from(n).ok()?;
+                    let date = every.times(n)?.add_to(start)?;
+                    Some(PublishDate::Date(date))
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_eyre("The schedule is out of range")?
+        }
+    };
+
+    let parts = parts(title, sections, dates);
+
+    let stem = path
+        .file_stem()
+        .ok_or_eyre("The file has no name")?
+        .to_string_lossy();
+    let width = parts.len().to_string().len();
+    let files = (1..=parts.len())
+        .map(|n| path.with_file_name(format!("{stem}-part-{n:0width$}.md")))
+        .collect::<Vec<PathBuf>>();
+    for file in &files {
+        ensure!(!file.exists(), "'{}' already exists", file.display());
+    }
+
+    for (idx, (part, file)) in parts.iter().zip(&files).enumerate() {
+        let content = write(&content, title, &parts, idx)?;
+        let date = match part.published_at {
+            PublishDate::Date(date) => date.format("%Y-%m-%d").to_string(),
+            PublishDate::Draft => String::from("draft"),
+        };
+        if opts.dry_run {
+            println!("Would write {}: {} [{date}]", file.display(), part.title);
+            continue;
+        }
+        fs::write(file, content)
+            .wrap_err_with(|| format!("Failed to write '{}'", file.display()))?;
+        println!("Wrote {}: {} [{date}]", file.display(), part.title);
+    }
+    Ok(())
+}
+
+const fn level(heading: Heading) -> HeadingLevel {
+    match heading {
+        Heading::H1 => HeadingLevel::H1,
+        Heading::H2 => HeadingLevel::H2,
+        Heading::H3 => HeadingLevel::H3,
+        Heading::H4 => HeadingLevel::H4,
+        Heading::H5 => HeadingLevel::H5,
+        Heading::H6 => HeadingLevel::H6,
+    }
+}
+
+/// Splits `body` before every heading of level `at` or higher, except the
+/// first one, so that the text before it stays with the first section.
+///
+/// Reference and footnote definitions are copied into the sections that use them.
+fn sections(body: &str, at: HeadingLevel) -> Vec<String> {
+    let parser = Parser::new_ext(body, Options::all());
+    let mut definitions = parser
+        .reference_definitions()
+        .iter()
+        .map(|(label, def)| (format!("[{}]", label.to_lowercase()), def.span.clone()))
+        .collect::<Vec<_>>();
+
+    let mut starts = Vec::new();
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading(level, ..)) if level <= at => starts.push(range.start),
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                definitions.push((format!("[^{}]", label.to_lowercase()), range));
+            }
+            _ => {}
+        }
+    }
+    let mut bounds = vec![0];
+    bounds.extend(starts.into_iter().skip(1));
+    bounds.push(body.len());
+
+    bounds
+        .windows(2)
+        .map(|range| {
+            let (start, end) = (range[0], range[1]);
+            let mut section = body[start..end].trim().to_owned();
+            let lowercase = section.to_lowercase();
+            for (label, span) in &definitions {
+                let inside = span.start >= start && span.end <= end;
+                if !inside && lowercase.contains(label.as_str()) {
+                    section.push_str("\n\n");
+                    section.push_str(body[span.clone()].trim());
+                }
+            }
+            section
+        })
+        .collect()
+}
+
+/// The parts with the given sections as their bodies, named after the post.
+///
+/// The slugs are the ones that mataroa derives from the titles, since it
+/// ignores the slug when a post is created, and the parts link to each other.
+fn parts(title: &str, sections: Vec<String>, dates: Vec<PublishDate>) -> Vec<Part> {
+    (1..)
+        .zip(sections.into_iter().zip(dates))
+        .map(|(n, (body, published_at))| {
+            let title = format!("{title} (Part {n})");
+            let slug = slug::slugify(&title);
+            Part {
+                title,
+                slug,
+                published_at,
+                body,
+            }
+        })
+        .collect()
+}
+
+/// The post file of the part at `idx`, with the frontmatter of the original
+/// post file `content`, and linked to the other parts of the series `name`.
+fn write(content: &str, name: &str, parts: &[Part], idx: usize) -> Result<String> {
+    let part = &parts[idx];
+    let head = frontmatter::rewrite(content, |fields| {
+        let _ = fields.remove("url");
+        let _ = fields.insert(String::from("title"), part.title.clone().into());
+        let _ = fields.insert(String::from("slug"), part.slug.clone().into());
+        let published_at = match part.published_at {
+            PublishDate::Date(date) => date.format("%Y-%m-%d").to_string(),
+            PublishDate::Draft => String::new(),
+        };
+        let _ = fields.insert(String::from("published_at"), published_at.into());
+        let _ = fields.insert(
+            String::from("series"),
+            serde_json::json!({ "name": name, "order": idx + 1 }),
+        );
+    })?;
+
+    let links = parts
+        .iter()
+        .map(|part| (part.title.clone(), format!("/blog/{}/", part.slug)))
+        .collect::<Vec<_>>();
+    let body = series::navigate(&part.body, name, &links, idx);
+    Ok(format!("{head}\n{body}\n"))
+}
+
+#[test]
+fn split_parts() {
+    let content = concat!(
+        "# Long Post\n\n",
+        "```yml\nslug: \"long\"\nurl: \"https://x\"\nvars:\n  a: \"b\"\n```\n\n",
+        "Intro with a [link].\n\n",
+        "## One\n\nFirst.\n\n",
+        "### Sub\n\nStill first.\n\n",
+        "## Two\n\n```md\n## Not a heading\n```\n\n",
+        "[link]: https://example.com\n",
+    );
+    let body = &content[frontmatter::body_start(content)..];
+
+    let sections = sections(body.trim(), HeadingLevel::H2);
+    assert_eq!(sections.len(), 2);
+    assert!(sections[0].starts_with("Intro with a [link]."));
+    assert!(sections[0].ends_with("Still first.\n\n[link]: https://example.com"));
+    assert!(sections[1].starts_with("## Two"));
+
+    let body = concat!(
+        "Intro[^note].\n\n",
+        "[^note]: A footnote\n    over two lines.\n\n",
+        "## One\n\nFirst.\n\n",
+        "## Two\n\nAgain[^Note].\n\n",
+        "## Three\n\nNone here.\n",
+    );
+    let sections = self::sections(body, HeadingLevel::H2);
+    assert_eq!(sections.len(), 3);
+    assert_eq!(sections[0].matches("[^note]:").count(), 1);
+    assert!(sections[1].ends_with("Again[^Note].\n\n[^note]: A footnote\n    over two lines."));
+    assert_eq!(sections[2], "## Three\n\nNone here.");
+
+    let dates = vec![
+        PublishDate::Date(chrono::NaiveDate::from_ymd_opt(2015, 10, 21).unwrap()),
+        PublishDate::Draft,
+    ];
+    let parts = parts("Long Post", sections, dates);
+    assert_eq!(parts[1].title, "Long Post (Part 2)");
+    assert_eq!(parts[1].slug, "long-post-part-2");
+
+    let first = write(content, "Long Post", &parts, 0).unwrap();
+    assert!(first.starts_with(concat!(
+        "```yml\n",
+        "title: \"Long Post (Part 1)\"\n",
+        "slug: \"long-post-part-1\"\n",
+        "published_at: \"2015-10-21\"\n",
+        "series:\n  name: \"Long Post\"\n  order: 1\n",
+        "vars:\n  a: \"b\"\n",
+        "```\n\n",
+        "<!-- roa:series-header -->\n*Part 1 of 2 of the series \"Long Post\"*\n",
+    )));
+    assert!(first.contains("[Next: Long Post (Part 2) →](/blog/long-post-part-2/)"));
+    assert!(!first.contains("https://x"));
+}
@@ -0,0 +1,463 @@
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    NaiveDate,
+};
+use kommandozeile::{
+    color_eyre::eyre::{bail, eyre, WrapErr as _},
+    Result,
+};
+
+use crate::{
+    config::{Config, Shortcode},
+    dateformat::{self, PublishDate},
+    links, preview, PostInput,
+};
+
+// Post bodies can use template expressions in double braces:
+//
+// {{ title }}                           a variable
+// {{ published_at | date("%B %Y") }}    a variable, passed through filters
+// {{ env.HOME }}                        an environment variable
+// {{ youtube("id", title="A video") }}  a shortcode from the config
+//
+// An expression can be escaped as `\{{ ... }}`.
+// Double braces that are not followed by a name or a string (like the
+// `{{#include ...}}` directive), and double braces in code in the post body,
+// are kept as they are.
+
+/// Shortcodes can use other shortcodes, but only this deep.
+const MAX_DEPTH: usize = 16;
+
+/// Expands all template expressions in the body of `post`.
+///
+/// The variables are the `title`, `slug`, `published_at`, and `url` of the
+/// post, the `vars` from its frontmatter, and `today`.
+/// Using an undefined variable is an error, unless it has a `default`.
+pub fn expand(post: &mut PostInput, config: &Config) -> Result<()> {
+    let Some(body) = post.body.as_deref().filter(|b| b.contains("{{")) else {
+        return Ok(());
+    };
+
+    let mut vars = post.vars.clone();
+    let builtins = [
+        ("title", post.title.clone()),
+        ("slug", post.slug.clone()),
+        ("url", post.url.clone()),
+        (
+            "published_at",
+            match post.published_at {
+                Some(PublishDate::Date(date)) => Some(date.format("%Y-%m-%d").to_string()),
+                Some(PublishDate::Draft) | None => None,
+            },
+        ),
+        (
+            "today",
+            Some(
+                dateformat::today(config.timezone)
+                    .format("%Y-%m-%d")
+                    .to_string(),
+            ),
+        ),
+    ];
+    for (name, value) in builtins {
+        if let Some(value) = value {
+            let _ = vars.insert(name.to_owned(), value);
+        }
+    }
+
+    let expander = Expander {
+        config,
+        keep_failing: false,
+        skip_code: true,
+    };
+    post.body = Some(expander.expand(body, &vars, 0)?);
+    Ok(())
+}
+
+/// Expands all template expressions in `text`, which can use the given `vars`.
+pub fn render(text: &str, vars: &BTreeMap<String, String>, config: &Config) -> Result<String> {
+    let expander = Expander {
+        config,
+        keep_failing: false,
+        skip_code: false,
+    };
+    expander.expand(text, vars, 0)
+}
+
+/// Expands the expressions in `text` that only use the given `vars`.
+///
+/// All other expressions, escaped ones, and shortcodes are kept as they are,
+/// so that they are expanded when the post is published.
+/// This is used for the templates of `roa new --template`.
+pub fn expand_known(text: &str, vars: &BTreeMap<String, String>) -> String {
+    let config = Config::default();
+    let expander = Expander {
+        config: &config,
+        keep_failing: true,
+        skip_code: true,
+    };
+    expander
+        .expand(text, vars, 0)
+        .unwrap_or_else(|_| text.to_owned())
+}
+
+struct Expander<'a> {
+    config: &'a Config,
+    /// Keep expressions that cannot be evaluated instead of failing.
+    keep_failing: bool,
+    /// Keep expressions in code spans and code blocks of the text, but not
+    /// in the bodies of shortcodes.
+    skip_code: bool,
+}
+
+impl Expander<'_> {
+    fn expand(&self, text: &str, vars: &BTreeMap<String, String>, depth: usize) -> Result<String> {
+        let code = if self.skip_code && depth == 0 {
+            preview::code_ranges(text)
+        } else {
+            Vec::new()
+        };
+        let mut out = String::with_capacity(text.len());
+        let mut at = 0;
+
+        while let Some(offset) = text[at..].find("{{") {
+            let start = at + offset;
+            if let Some(code) = code.iter().find(|c| c.contains(&start)) {
+                out.push_str(&text[at..code.end]);
+                at = code.end;
+                continue;
+            }
+            out.push_str(&text[at..start]);
+            at = start + 2;
+
+            if text[..start].ends_with('\\') {
+                if !self.keep_failing {
+                    let _ = out.pop();
+                }
+                out.push_str("{{");
+                continue;
+            }
+
+            let rest = text[at..].trim_start();
+            if !rest.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '"' || c == '\'') {
+                out.push_str("{{");
+                continue;
+            }
+
+            let Some(len) = text[at..].find("}}") else {
+                bail!(
+                    "line {}: unclosed template expression",
+                    links::line_of(text, start)
+                );
+            };
+            let source = &text[at..at + len];
+            at += len + 2;
+
+            match self.eval(source, vars, depth) {
+                Ok(value) => out.push_str(&value),
+                Err(_) if self.keep_failing => {
+                    out.push_str(&text[start..at]);
+                }
+                Err(e) => {
+                    return Err(e.wrap_err(format!(
+                        "line {}: {{{{{source}}}}}",
+                        links::line_of(text, start)
+                    )))
+                }
+            }
+        }
+
+        out.push_str(&text[at..]);
+        Ok(out)
+    }
+
+    fn eval(&self, source: &str, vars: &BTreeMap<String, String>, depth: usize) -> Result<String> {
+        let mut tokens = Tokens::new(source)?;
+        let mut variable = "";
+
+        let mut value = match tokens.next() {
+            Some(Token::Str(s)) => Some(s),
+            Some(Token::Name(name)) if tokens.peek() == Some(&Token::Open) => {
+                let args = tokens.args(vars)?;
+                Some(self.shortcode(name, args, vars, depth)?)
+            }
+            Some(Token::Name(name)) => {
+                variable = name;
+                lookup(name, vars)
+            }
+            _ => bail!("expected a variable, a string, or a shortcode"),
+        };
+
+        while let Some(token) = tokens.next() {
+            let Token::Pipe = token else {
+                bail!("expected a filter after the value");
+            };
+            let Some(Token::Name(filter)) = tokens.next() else {
+                bail!("expected the name of a filter after '|'");
+            };
+            let args = if tokens.peek() == Some(&Token::Open) {
+                tokens.args(vars)?
+            } else {
+                Args::default()
+            };
+            value = apply(filter, value, &args)?;
+        }
+
+        value.ok_or_else(|| eyre!("undefined variable '{variable}'"))
+    }
+
+    fn shortcode(
+        &self,
+        name: &str,
+        args: Args,
+        vars: &BTreeMap<String, String>,
+        depth: usize,
+    ) -> Result<String> {
+        let Some(Shortcode { params, body }) = self.config.shortcodes.get(name) else {
+            bail!("unknown shortcode '{name}'");
+        };
+        if depth >= MAX_DEPTH {
+            bail!("shortcodes are nested too deeply, do they use each other?");
+        }
+        if args.positional.len() > params.len() {
+            bail!(
+                "'{name}' takes {} parameters, but {} were given",
+                params.len(),
+                args.positional.len()
+            );
+        }
+
+        // parameters that are not passed are undefined, even if a variable has the same name
+        let mut scope = vars.clone();
+        scope.retain(|var, _| !params.contains(var));
+        for (param, value) in params.iter().zip(args.positional) {
+            let _ = scope.insert(param.clone(), value);
+        }
+        for (param, value) in args.named {
+            if !params.contains(&param) {
+                bail!("'{name}' has no parameter '{param}'");
+            }
+            let _ = scope.insert(param, value);
+        }
+
+        let body = self
+            .expand(body, &scope, depth + 1)
+            .wrap_err_with(|| format!("in shortcode '{name}'"))?;
+        Ok(body.trim_end_matches('\n').to_owned())
+    }
+}
+
+fn lookup(name: &str, vars: &BTreeMap<String, String>) -> Option<String> {
+    name.strip_prefix("env.")
+        .map_or_else(|| vars.get(name).cloned(), |var| std::env::var(var).ok())
+}
+
+fn apply(filter: &str, value: Option<String>, args: &Args) -> Result<Option<String>> {
+    let arg = |idx: usize| {
+        args.positional
+            .get(idx)
+            .map(String::as_str)
+            .ok_or_else(|| eyre!("the filter '{filter}' needs an argument"))
+    };
+
+    Ok(match filter {
+        "default" => Some(value.map_or_else(|| arg(0).map(str::to_owned), Ok)?),
+        "upper" => value.map(|v| v.to_uppercase()),
+        "lower" => value.map(|v| v.to_lowercase()),
+        "trim" => value.map(|v| v.trim().to_owned()),
+        "date" => {
+            let format = arg(0)?;
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                bail!("'{format}' is not a valid date format");
+            }
+            match value {
+                Some(value) => {
+                    let date = NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                        .wrap_err_with(|| format!("'{value}' is not a date"))?;
+                    let mut out = String::new();
+                    write!(out, "{}", date.format(format))
+                        .map_err(|_| eyre!("'{format}' cannot be used to format a date"))?;
+                    Some(out)
+                }
+                None => None,
+            }
+        }
+        _ => bail!("unknown filter '{filter}'"),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    /// A variable, a shortcode, or a filter; can contain dots.
+    Name(&'a str),
+    Str(String),
+    Pipe,
+    Open,
+    Close,
+    Comma,
+    Eq,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Args {
+    positional: Vec<String>,
+    named: Vec<(String, String)>,
+}
+
+struct Tokens<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(source: &'a str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut chars = source.char_indices().peekable();
+
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                c if c.is_whitespace() => {}
+                '|' => tokens.push(Token::Pipe),
+                '(' => tokens.push(Token::Open),
+                ')' => tokens.push(Token::Close),
+                ',' => tokens.push(Token::Comma),
+                '=' => tokens.push(Token::Eq),
+                '"' | '\'' => {
+                    let mut s = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '\\')) => s.extend(chars.next().map(|(_, c)| c)),
+                            Some((_, q)) if q == c => break,
+                            Some((_, c)) => s.push(c),
+                            None => bail!("unclosed string"),
+                        }
+                    }
+                    tokens.push(Token::Str(s));
+                }
+                c if c.is_alphanumeric() || c == '_' => {
+                    let mut end = idx + c.len_utf8();
+                    while let Some((i, c)) =
+                        chars.next_if(|(_, c)| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'))
+                    {
+                        end = i + c.len_utf8();
+                    }
+                    let name = &source[idx..end];
+                    if c.is_ascii_digit() {
+                        tokens.push(Token::Str(name.to_owned()));
+                    } else {
+                        tokens.push(Token::Name(name));
+                    }
+                }
+                c => bail!("unexpected '{c}'"),
+            }
+        }
+
+        Ok(Self { tokens, pos: 0 })
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Parses `(arg, name=arg, ...)`, where every argument is a string or
+    /// a variable.
+    fn args(&mut self, vars: &BTreeMap<String, String>) -> Result<Args> {
+        let mut args = Args::default();
+        if self.next() != Some(Token::Open) {
+            bail!("expected '('");
+        }
+        if self.peek() == Some(&Token::Close) {
+            let _ = self.next();
+            return Ok(args);
+        }
+
+        loop {
+            let name = match self.tokens.get(self.pos..self.pos + 2) {
+                Some([Token::Name(name), Token::Eq]) => {
+                    self.pos += 2;
+                    Some(*name)
+                }
+                _ => None,
+            };
+
+            let value = match self.next() {
+                Some(Token::Str(s)) => s,
+                Some(Token::Name(var)) => {
+                    lookup(var, vars).ok_or_else(|| eyre!("undefined variable '{var}'"))?
+                }
+                _ => bail!("expected a string or a variable as argument"),
+            };
+            match name {
+                Some(name) => args.named.push((name.to_owned(), value)),
+                None => args.positional.push(value),
+            }
+
+            match self.next() {
+                Some(Token::Comma) => {}
+                Some(Token::Close) => return Ok(args),
+                _ => bail!("expected ',' or ')'"),
+            }
+        }
+    }
+}
+
+#[test]
+fn expand_templates() {
+    let config = Config {
+        shortcodes: [(
+            String::from("note"),
+            Shortcode {
+                params: vec![String::from("text"), String::from("kind")],
+                body: String::from("> **{{ kind | default(\"Note\") }}**: {{ text }}\n"),
+            },
+        )]
+        .into(),
+        ..Config::default()
+    };
+    let mut post = PostInput {
+        title: Some(String::from("Hello")),
+        published_at: Some(PublishDate::Date(
+            NaiveDate::from_ymd_opt(2015, 10, 21).unwrap(),
+        )),
+        vars: [(String::from("topic"), String::from("rust"))].into(),
+        body: Some(String::from(concat!(
+            "{{ title }} on {{ published_at | date(\"%B %Y\") }} about {{topic|upper}}.\n",
+            "{{ note(\"Read this\") }}\n",
+            "{{ note(text=title, kind=\"Tip\") }}\n",
+            "\\{{ title }} and {{#include x.md}}",
+        ))),
+        ..PostInput::default()
+    };
+
+    expand(&mut post, &config).unwrap();
+    assert_eq!(
+        post.body.as_deref().unwrap(),
+        concat!(
+            "Hello on October 2015 about RUST.\n",
+            "> **Note**: Read this\n",
+            "> **Tip**: Hello\n",
+            "{{ title }} and {{#include x.md}}",
+        )
+    );
+
+    post.body = Some(String::from(concat!(
+        "`{{ title }}` is {{ title }}\n\n",
+        "```md\n{{ title }} and {{ missing }}\n```\n",
+    )));
+    expand(&mut post, &config).unwrap();
+    assert_eq!(
+        post.body.as_deref().unwrap(),
+        "`{{ title }}` is Hello\n\n```md\n{{ title }} and {{ missing }}\n```\n",
+    );
+
+    post.body = Some(String::from("{{ missing }}"));
+    assert!(expand(&mut post, &config).is_err());
+}
@@ -0,0 +1,357 @@
+use std::{collections::BTreeMap, fmt::Write as _, ops::Range};
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde::Deserialize;
+
+use crate::{config::Config, preview, PostInput};
+
+/// Which transforms are applied to a post body before it is published.
+///
+/// Settings can be made in the config, per profile, and per post in the
+/// frontmatter; unset transforms are off.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Transforms {
+    /// Shift all headings so that the highest one is an h2.
+    pub shift_headings: Option<bool>,
+    /// Generate a table of contents at the `<!-- toc -->` marker.
+    pub toc: Option<bool>,
+    /// Number the footnotes in the order they are referenced and move their
+    /// definitions to the end.
+    pub footnotes: Option<bool>,
+    /// Use typographic quotes, dashes, and ellipses.
+    pub typography: Option<bool>,
+}
+
+impl Transforms {
+    /// The settings of `self`, and those of `other` where `self` has none.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            shift_headings: self.shift_headings.or(other.shift_headings),
+            toc: self.toc.or(other.toc),
+            footnotes: self.footnotes.or(other.footnotes),
+            typography: self.typography.or(other.typography),
+        }
+    }
+}
+
+const TOC_START: &str = "<!-- toc -->";
+const TOC_END: &str = "<!-- /toc -->";
+
+/// Applies the transforms that are enabled for `post`, either in its
+/// frontmatter or in the config.
+pub fn apply_to(post: &mut PostInput, config: &Config) {
+    let transforms = post.transforms.or(config.transforms);
+    if let Some(body) = &post.body {
+        post.body = Some(apply(body, transforms));
+    }
+}
+
+/// Applies the enabled transforms to `body`.
+/// All transforms are idempotent, so this can be done repeatedly.
+pub fn apply(body: &str, transforms: Transforms) -> String {
+    let mut body = body.to_owned();
+    if transforms.shift_headings == Some(true) {
+        body = shift_headings(&body);
+    }
+    if transforms.footnotes == Some(true) {
+        body = footnotes(&body);
+    }
+    if transforms.typography == Some(true) {
+        body = typography(&body);
+    }
+    if transforms.toc == Some(true) {
+        body = toc(&body);
+    }
+    body
+}
+
+fn events(body: &str, options: Options) -> impl Iterator<Item = (Event<'_>, Range<usize>)> {
+    Parser::new_ext(body, options).into_offset_iter()
+}
+
+/// Replaces the given ranges of `body`, which must not overlap.
+fn edit(body: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut body = body.to_owned();
+    for (range, replacement) in edits {
+        body.replace_range(range, &replacement);
+    }
+    body
+}
+
+fn shift_headings(body: &str) -> String {
+    let headings = events(body, preview::options())
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::Heading(level, ..)) => Some((level as usize, range)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let Some(highest) = headings.iter().map(|(level, _)| *level).min() else {
+        return body.to_owned();
+    };
+    if highest == 2 {
+        return body.to_owned();
+    }
+
+    let edits = headings
+        .into_iter()
+        .map(|(level, range)| {
+            let level = (level + 2).saturating_sub(highest).clamp(1, 6);
+            let hashes = "#".repeat(level);
+            let source = &body[range.clone()];
+            let indent = source.len() - source.trim_start_matches(' ').len();
+
+            if source[indent..].starts_with('#') {
+                let count = source[indent..].len() - source[indent..].trim_start_matches('#').len();
+                let start = range.start + indent;
+                (start..start + count, hashes)
+            } else {
+                // a setext heading, the last line is the underline
+                let text = source.trim_end().lines().collect::<Vec<_>>();
+                let text = text[..text.len().saturating_sub(1)]
+                    .iter()
+                    .map(|l| l.trim())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let newline = if source.ends_with('\n') { "\n" } else { "" };
+                (range, format!("{hashes} {text}{newline}"))
+            }
+        })
+        .collect();
+
+    edit(body, edits)
+}
+
+fn footnotes(body: &str) -> String {
+    let mut numbers = BTreeMap::new();
+    let mut defined = Vec::new();
+    let mut references = Vec::new();
+
+    for (event, range) in events(body, preview::options()) {
+        match event {
+            Event::FootnoteReference(label) => references.push((label.into_string(), range)),
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                defined.push((label.into_string(), range));
+            }
+            _ => {}
+        }
+    }
+
+    if defined.is_empty() {
+        return body.to_owned();
+    }
+
+    for (label, _) in &references {
+        if defined.iter().any(|(l, _)| l == label) && !numbers.contains_key(label) {
+            let _ = numbers.insert(label.clone(), numbers.len() + 1);
+        }
+    }
+    // definitions that are never referenced go last
+    for (label, _) in &defined {
+        if !numbers.contains_key(label) {
+            let _ = numbers.insert(label.clone(), numbers.len() + 1);
+        }
+    }
+
+    let mut definitions = defined
+        .iter()
+        .map(|(label, range)| {
+            let source = body[range.clone()].trim_end();
+            let text = source.split_once("]:").map_or("", |(_, text)| text);
+            (numbers[label], format!("[^{}]:{text}", numbers[label]))
+        })
+        .collect::<Vec<_>>();
+    definitions.sort_by_key(|(number, _)| *number);
+
+    let mut edits = references
+        .into_iter()
+        .filter(|(label, _)| numbers.contains_key(label))
+        .filter(|(_, range)| !defined.iter().any(|(_, def)| def.contains(&range.start)))
+        .map(|(label, range)| (range, format!("[^{}]", numbers[&label])))
+        .collect::<Vec<_>>();
+    edits.extend(defined.into_iter().map(|(_, range)| {
+        let blank = body[range.end..].len() - body[range.end..].trim_start_matches('\n').len();
+        (range.start..range.end + blank, String::new())
+    }));
+
+    let mut body = edit(body, edits).trim_end().to_owned();
+    body.push_str("\n\n");
+    for (_, definition) in definitions {
+        // references in definitions are renumbered here, since the
+        // definitions are moved as a whole
+        let definition = renumber_references(&definition, &numbers);
+        body.push_str(&definition);
+        body.push('\n');
+    }
+    body.trim_end().to_owned()
+}
+
+fn renumber_references(text: &str, numbers: &BTreeMap<String, usize>) -> String {
+    let (head, mut tail) = text.split_once("]:").unwrap_or((text, ""));
+    let mut out = format!("{head}]:");
+    while let Some(start) = tail.find("[^") {
+        let label = tail[start + 2..].split_once(']').map(|(label, _)| label);
+        if let Some((label, number)) = label.and_then(|label| Some((label, numbers.get(label)?))) {
+            let _ = write!(out, "{}[^{number}]", &tail[..start]);
+            tail = &tail[start + label.len() + 3..];
+        } else {
+            out.push_str(&tail[..start + 2]);
+            tail = &tail[start + 2..];
+        }
+    }
+    out.push_str(tail);
+    out
+}
+
+fn typography(body: &str) -> String {
+    let options = preview::options() | Options::ENABLE_SMART_PUNCTUATION;
+    let templates = template_spans(body);
+
+    let edits = events(body, options)
+        .filter_map(|(event, range)| match event {
+            Event::Text(text)
+                if matches!(&body[range.clone()], "\"" | "'" | "--" | "---" | "...") =>
+            {
+                (*text != body[range.clone()]).then(|| (range, text.into_string()))
+            }
+            _ => None,
+        })
+        .filter(|(range, _)| !templates.iter().any(|t| t.contains(&range.start)))
+        .collect();
+
+    edit(body, edits)
+}
+
+/// Template expressions are not transformed, so that `roa fmt` keeps them intact.
+pub fn template_spans(body: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut at = 0;
+    while let Some(start) = body[at..].find("{{").map(|s| s + at) {
+        let Some(end) = body[start..].find("}}").map(|e| e + start + 2) else {
+            break;
+        };
+        spans.push(start..end);
+        at = end;
+    }
+    spans
+}
+
+/// Escapes `text` so that it can be used as the text of a link,
+/// or anywhere else in Markdown where it should not be formatted.
+pub fn link_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn toc(body: &str) -> String {
+    let events = events(body, preview::options()).collect::<Vec<_>>();
+
+    let marker = |text: &str| {
+        events.iter().find_map(|(event, range)| match event {
+            Event::Html(html) if html.trim() == text => Some(range.clone()),
+            _ => None,
+        })
+    };
+    let Some(start) = marker(TOC_START) else {
+        return body.to_owned();
+    };
+    let end = marker(TOC_END).filter(|end| end.start >= start.end);
+    let generated = start.start..end.map_or(start.end, |end| end.end);
+
+    let mut used = Vec::new();
+    let mut headings = Vec::new();
+    let mut current = None;
+    for (event, range) in &events {
+        match event {
+            Event::Start(Tag::Heading(level, ..)) => {
+                current = Some((*level as usize, String::new(), range.start));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading, _)) = &mut current {
+                    heading.push_str(text);
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                if let Some((level, text, at)) = current.take() {
+                    let id = preview::unique_anchor(&text, &mut used);
+                    if !generated.contains(&at) {
+                        headings.push((level, text, id));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let highest = headings.iter().map(|(level, ..)| *level).min().unwrap_or(1);
+    let mut toc = format!("{TOC_START}\n\n");
+    for (level, text, id) in headings {
+        let indent = "  ".repeat(level - highest);
+        let _ = writeln!(toc, "{indent}- [{}](#{id})", link_text(&text));
+    }
+    let _ = writeln!(toc, "\n{TOC_END}");
+    if !body[generated.end..].starts_with('\n') {
+        toc.push('\n');
+    }
+
+    edit(body, vec![(generated, toc)])
+}
+
+#[test]
+fn transform_bodies() {
+    let all = Transforms {
+        shift_headings: Some(true),
+        toc: Some(true),
+        footnotes: Some(true),
+        typography: Some(true),
+    };
+
+    let body = concat!(
+        "<!-- toc -->\n",
+        "\n",
+        "# Intro\n",
+        "\n",
+        "It's \"quoted\"[^b] -- and more...[^a]\n",
+        "\n",
+        "[^a]: First.\n",
+        "\n",
+        "Details\n",
+        "-------\n",
+        "\n",
+        "[^b]: Second.\n",
+        "\n",
+        "`\"code\"` and {{ x | date(\"%Y\") }}\n",
+    );
+
+    let expected = concat!(
+        "<!-- toc -->\n",
+        "\n",
+        "- [Intro](#intro)\n",
+        "  - [Details](#details)\n",
+        "\n",
+        "<!-- /toc -->\n",
+        "\n",
+        "## Intro\n",
+        "\n",
+        "It’s “quoted”[^1] – and more…[^2]\n",
+        "\n",
+        "### Details\n",
+        "\n",
+        "`\"code\"` and {{ x | date(\"%Y\") }}\n",
+        "\n",
+        "[^1]: Second.\n",
+        "[^2]: First.",
+    );
+
+    let transformed = apply(body, all);
+    assert_eq!(transformed, expected);
+    assert_eq!(apply(&transformed, all), expected);
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono_tz::Tz;
+use kommandozeile::{
+    color_eyre::{
+        eyre::{ensure, OptionExt as _},
+        Report,
+    },
+    tracing::{debug, warn},
+    InputFile, Result,
+};
+
+use crate::{
+    api::{self, Action as _, Opts},
+    args,
+    config::Config,
+    frontmatter, series,
+    watcher::{Change, Watcher},
+    wikilinks, Post, PostInput,
+};
+
+/// How often the watched files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The longest time to wait between two attempts of a failed push.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Watches post files and pushes every saved change to mataroa.
+///
+/// Files are parsed like the input of `create` and `update`.
+/// Files with a slug update the existing post, files without one create a
+/// new post and get the slug that was assigned by mataroa written back into
+/// their frontmatter.
+/// A push is only made if the parsed content differs from the last push (or
+/// from the content the file had when the watch started).
+pub fn run(client: &api::Client, opts: Opts, config: &Config, cmd: &args::Watch) -> Result<()> {
+    // the slugs of created posts are needed to update them on the next save
+    ensure!(!opts.print_json, "--json is not supported by watch");
+    let debounce = Duration::from_millis(cmd.debounce);
+    let mut watcher = Watcher::new(&cmd.path)?;
+
+    let mut pushed = BTreeMap::new();
+    for file in watcher.files() {
+        match parse(file, config.timezone) {
+            Ok(post) => {
+                let _ = pushed.insert(file.to_path_buf(), post);
+            }
+            Err(err) => warn!(file = %file.display(), ?err, "Failed to parse post file"),
+        }
+    }
+
+    println!("Watching {} for changes", cmd.path.display());
+
+    let mut pending = BTreeMap::<PathBuf, Instant>::new();
+    let mut retries = VecDeque::<Retry>::new();
+
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        for change in watcher.poll()? {
+            match change {
+                Change::Created(path) | Change::Modified(path) => {
+                    let _ = pending.insert(path, Instant::now());
+                }
+                Change::Removed(path) => {
+                    let _ = pending.remove(&path);
+                    let _ = pushed.remove(&path);
+                    retries.retain(|r| r.path != path);
+                }
+            }
+        }
+
+        let now = Instant::now();
+
+        let due = pending
+            .iter()
+            .filter(|(_, saved)| now.duration_since(**saved) >= debounce)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+
+        for path in due {
+            let _ = pending.remove(&path);
+            // a new save supersedes any retry of an older version
+            retries.retain(|r| r.path != path);
+            push_or_retry(client, opts, config, &path, &mut pushed, &mut retries, 0);
+        }
+
+        while retries.front().is_some_and(|r| r.next_attempt <= now) {
+            let Some(retry) = retries.pop_front() else {
+                break;
+            };
+            push_or_retry(
+                client,
+                opts,
+                config,
+                &retry.path,
+                &mut pushed,
+                &mut retries,
+                retry.attempts,
+            );
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Retry {
+    path: PathBuf,
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+fn push_or_retry(
+    client: &api::Client,
+    opts: Opts,
+    config: &Config,
+    path: &Path,
+    pushed: &mut BTreeMap<PathBuf, PostInput>,
+    retries: &mut VecDeque<Retry>,
+    attempts: u32,
+) {
+    let err = match push(client, opts, config, path, pushed, attempts > 0) {
+        Ok(()) => return,
+        Err(err) => err,
+    };
+
+    if !is_transient(&err) {
+        warn!(file = %path.display(), ?err, "Failed to push post");
+        return;
+    }
+
+    let attempts = attempts + 1;
+    let backoff = Duration::from_secs(1 << attempts.min(6)).min(MAX_BACKOFF);
+    warn!(
+        file = %path.display(),
+        err = %format!("{err:#}"),
+        retry_in = ?backoff,
+        "Failed to push post, will retry",
+    );
+
+    let retry = Retry {
+        path: path.to_path_buf(),
+        attempts,
+        next_attempt: Instant::now() + backoff,
+    };
+    let at = retries
+        .iter()
+        .position(|r| r.next_attempt > retry.next_attempt)
+        .unwrap_or(retries.len());
+    retries.insert(at, retry);
+}
+
+fn push(
+    client: &api::Client,
+    opts: Opts,
+    config: &Config,
+    path: &Path,
+    pushed: &mut BTreeMap<PathBuf, PostInput>,
+    retry: bool,
+) -> Result<()> {
+    let post = parse(path, config.timezone)?;
+    let previous = pushed.get(path);
+
+    if previous == Some(&post) {
+        debug!(file = %path.display(), "Unchanged, not pushing");
+        return Ok(());
+    }
+
+    // templates and wiki-links are resolved for every push,
+    // but the file is compared as written
+    let mut resolved = post.clone();
+    crate::resolve_date(client, config, &mut resolved)?;
+    resolved.render(config)?;
+    wikilinks::resolve(
+        client,
+        opts,
+        &mut resolved,
+        Some(&InputFile::File(path.to_path_buf())),
+    )?;
+
+    // a created post whose slug could not be written back is still updated
+    let slug = post
+        .slug
+        .clone()
+        .or_else(|| previous.and_then(|p| p.slug.clone()));
+    let Some(slug) = slug else {
+        let created = create(client, opts, path, resolved, retry)?;
+        return record(path, post, created.as_ref(), pushed, config.timezone);
+    };
+
+    // if the slug was changed in the file, the post is found by the old slug
+    let (slug, updated_slug) = match previous.and_then(|p| p.slug.as_ref()) {
+        Some(old) if *old != slug => (old.clone(), Some(slug)),
+        _ => (slug, None),
+    };
+
+    series::keep_on_update(client, opts, &mut resolved, &slug)?;
+
+    api::Update::builder()
+        .slug(slug)
+        .title(post.title.clone())
+        .updated_slug(updated_slug)
+        .body(resolved.body)
+        .published_at(resolved.published_at)
+        .build()
+        .run(client, opts, |updated| {
+            println!("Updated {} from {}", updated.slug, path.display());
+            Ok(())
+        })?;
+
+    let _ = pushed.insert(path.to_path_buf(), post);
+    Ok(())
+}
+
+/// Creates the post, or only prints the request with `--dry-run`.
+fn create(
+    client: &api::Client,
+    opts: Opts,
+    path: &Path,
+    post: PostInput,
+    retry: bool,
+) -> Result<Option<Post>> {
+    let title = post.title.clone().ok_or_eyre(concat!(
+        "The title is required to create a new post. ",
+        "It can be provided as the title key in the post frontmatter, ",
+        "or as a leading h1 before the post frontmatter"
+    ))?;
+
+    let create = api::Create::builder()
+        .title(title.clone())
+        .body(post.body)
+        .published_at(post.published_at)
+        .build();
+    if opts.dry_run {
+        create.run(client, opts, |_| Ok(()))?;
+        return Ok(None);
+    }
+
+    // a failed attempt might have created the post anyway (e.g. if only the
+    // response got lost), so a retry looks for it first to not create it twice
+    if retry {
+        let existing = client
+            .call(api::List {})?
+            .into_iter()
+            .find(|p| p.title.as_deref() == Some(title.as_str()));
+        if let Some(existing) = existing {
+            println!("Found {} for {}", existing.slug, path.display());
+            return Ok(Some(existing));
+        }
+    }
+
+    let created = client.call(create)?;
+    println!("Created {} from {}", created.slug, path.display());
+    Ok(Some(created))
+}
+
+/// Records the pushed `post` and writes the slug of the `created` post back
+/// into the file.
+fn record(
+    path: &Path,
+    mut post: PostInput,
+    created: Option<&Post>,
+    pushed: &mut BTreeMap<PathBuf, PostInput>,
+    tz: Option<Tz>,
+) -> Result<()> {
+    let Some(created) = created else {
+        let _ = pushed.insert(path.to_path_buf(), post);
+        return Ok(());
+    };
+
+    // the slug is recorded before it is written back, so that the next save
+    // updates the post, even if the file cannot be written
+    post.slug = Some(created.slug.clone());
+    let _ = pushed.insert(path.to_path_buf(), post);
+    frontmatter::write_back(path, created)?;
+
+    // records the file with the written back frontmatter,
+    // so that the resulting change does not trigger another push
+    if let Ok(post) = parse(path, tz) {
+        let _ = pushed.insert(path.to_path_buf(), post);
+    }
+    Ok(())
+}
+
+fn parse(path: &Path, tz: Option<Tz>) -> Result<PostInput> {
+    PostInput::from_file(&InputFile::File(path.to_path_buf()), tz)
+}
+
+/// Network errors and server errors might go away on their own,
+/// everything else (e.g. an invalid post or a missing slug) will not.
+fn is_transient(err: &Report) -> bool {
+    match err.downcast_ref::<ureq::Error>() {
+        Some(ureq::Error::Transport(_)) => true,
+        Some(ureq::Error::Status(status, _)) => *status == 429 || *status >= 500,
+        None => false,
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use kommandozeile::Result;
+
+/// A change to one of the watched files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Created(PathBuf),
+    Modified(PathBuf),
+    Removed(PathBuf),
+}
+
+impl Change {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Created(path) | Self::Modified(path) | Self::Removed(path) => path,
+        }
+    }
+}
+
+/// Watches a post file or a directory of post files for changes.
+///
+/// Changes are detected by polling the modification times of the files,
+/// which is good enough for the handful of files that make up a blog and
+/// works the same on every platform.
+/// Directories are scanned recursively for markdown files, skipping hidden
+/// files and directories.
+#[derive(Clone, Debug)]
+pub struct Watcher {
+    root: PathBuf,
+    files: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Watcher {
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        let files = scan(&root)?;
+        Ok(Self { root, files })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// All files that are currently watched.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(PathBuf::as_path)
+    }
+
+    /// Rescans the watched files and returns everything that changed since
+    /// the last call.
+    pub fn poll(&mut self) -> Result<Vec<Change>> {
+        let files = scan(&self.root)?;
+        let mut changes = Vec::new();
+
+        for (path, modified) in &files {
+            match self.files.get(path) {
+                None => changes.push(Change::Created(path.clone())),
+                Some(previous) if previous != modified => {
+                    changes.push(Change::Modified(path.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+
+        changes.extend(
+            self.files
+                .keys()
+                .filter(|path| !files.contains_key(*path))
+                .cloned()
+                .map(Change::Removed),
+        );
+
+        self.files = files;
+        Ok(changes)
+    }
+}
+
+pub fn is_post_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+}
+
+fn scan(root: &Path) -> Result<BTreeMap<PathBuf, Option<SystemTime>>> {
+    let mut files = BTreeMap::new();
+    if root.is_dir() {
+        scan_dir(root, &mut files)?;
+    } else {
+        let modified = fs::metadata(root).and_then(|m| m.modified()).ok();
+        let _ = files.insert(root.to_path_buf(), modified);
+    }
+    Ok(files)
+}
+
+fn scan_dir(dir: &Path, files: &mut BTreeMap<PathBuf, Option<SystemTime>>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let path = entry.path();
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            scan_dir(&path, files)?;
+        } else if is_post_file(&path) {
+            let _ = files.insert(path, meta.modified().ok());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn poll_changes() {
+    let root = std::env::temp_dir().join(format!("roa-watcher-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join(".hidden")).unwrap();
+    fs::create_dir_all(root.join("nested")).unwrap();
+    let (post, nested) = (root.join("post.md"), root.join("nested/other.markdown"));
+    fs::write(&post, "Post").unwrap();
+    fs::write(root.join("notes.txt"), "Notes").unwrap();
+    fs::write(root.join(".hidden/draft.md"), "Draft").unwrap();
+
+    let mut watcher = Watcher::new(&root).unwrap();
+    assert_eq!(watcher.files().collect::<Vec<_>>(), [post.as_path()]);
+    assert_eq!(watcher.poll().unwrap(), []);
+
+    fs::write(&nested, "Other").unwrap();
+    let modified = SystemTime::now() + std::time::Duration::from_secs(60);
+    fs::File::options()
+        .write(true)
+        .open(&post)
+        .and_then(|file| file.set_modified(modified))
+        .unwrap();
+    assert_eq!(
+        watcher.poll().unwrap(),
+        [Change::Created(nested), Change::Modified(post.clone())]
+    );
+
+    fs::remove_file(&post).unwrap();
+    assert_eq!(watcher.poll().unwrap(), [Change::Removed(post)]);
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,320 @@
+use std::{collections::BTreeMap, fmt::Write as _, fs, ops::Range, path::Path};
+
+use kommandozeile::{color_eyre::eyre::bail, tracing::warn, InputFile, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use pulldown_cmark::{Event, LinkType, Parser, Tag};
+
+use crate::{
+    api::{self, Opts},
+    links::{self, Blog},
+    preview, watcher, Post, PostInput,
+};
+
+/// Characters that are escaped in the destination of a translated embed.
+const DEST: &AsciiSet = &CONTROLS.add(b' ').add(b'(').add(b')').add(b'<').add(b'>');
+
+/// A wiki-link in the style of Obsidian:
+/// `[[target]]`, `[[target#heading|label]]`, or `![[target]]` for embeds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct WikiLink<'a> {
+    embed: bool,
+    /// The name of the linked note, empty for links to a heading of the same note.
+    target: &'a str,
+    heading: Option<&'a str>,
+    label: Option<&'a str>,
+    /// The whole link, including the brackets.
+    range: Range<usize>,
+}
+
+impl WikiLink<'_> {
+    /// What the link shows if it has no label, like Obsidian does.
+    fn text(&self) -> String {
+        match (self.target, self.heading) {
+            (target, None) => target.to_owned(),
+            ("", Some(heading)) => heading.to_owned(),
+            (target, Some(heading)) => format!("{target} > {heading}"),
+        }
+    }
+
+    /// Embeds of anything but notes are kept as images.
+    fn is_attachment(&self) -> bool {
+        Path::new(self.target)
+            .extension()
+            .is_some_and(|_| !watcher::is_post_file(Path::new(self.target)))
+    }
+}
+
+/// Finds all wiki-links in `body`, except in code.
+fn find(body: &str) -> Vec<WikiLink<'_>> {
+    let code = preview::code_ranges(body);
+
+    let mut found = Vec::new();
+    let mut at = 0;
+    while let Some(offset) = body[at..].find("[[") {
+        let start = at + offset;
+        at = start + 2;
+
+        if let Some(block) = code.iter().find(|c| c.contains(&start)) {
+            at = block.end;
+            continue;
+        }
+
+        let Some(len) = body[at..].find("]]") else {
+            break;
+        };
+        let inner = &body[at..at + len];
+        if inner.contains(['\n', '[']) {
+            continue;
+        }
+
+        let embed = body[..start].ends_with('!');
+        let (inner, label) = match inner.split_once('|') {
+            Some((inner, label)) => (inner, Some(label.trim())),
+            None => (inner, None),
+        };
+        let (target, heading) = match inner.split_once('#') {
+            Some((target, heading)) => (target.trim(), Some(heading.trim())),
+            None => (inner.trim(), None),
+        };
+
+        at += len + 2;
+        found.push(WikiLink {
+            embed,
+            target,
+            heading,
+            label,
+            range: if embed { start - 1 } else { start }..at,
+        });
+    }
+
+    found
+}
+
+/// Replaces all wiki-links in the body of `post` with markdown links to the
+/// posts they point to.
+///
+/// Targets are matched against the names of the post files next to `file`
+/// (which must have been created already, so that they have a slug), and
+/// then against the slugs and titles of the posts on mataroa.
+/// Embeds of notes become links, embeds of anything else become images.
+/// Fails if any target cannot be resolved.
+///
+/// In a dry run, the posts on mataroa are not looked up, and the wiki-links
+/// that point to them are kept as they are.
+pub fn resolve(
+    client: &api::Client,
+    opts: Opts,
+    post: &mut PostInput,
+    file: Option<&InputFile>,
+) -> Result<()> {
+    let Some(body) = post.body.as_deref() else {
+        return Ok(());
+    };
+    let found = find(body);
+    if found.is_empty() {
+        return Ok(());
+    }
+
+    // a post from stdin has no files next to it
+    let mut slugs = match file {
+        Some(InputFile::File(path)) => {
+            let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+            local_slugs(dir.unwrap_or_else(|| Path::new(".")), &found)?
+        }
+        _ => BTreeMap::new(),
+    };
+    let remote = if opts.dry_run {
+        warn!("The posts on mataroa are not looked up in a dry run, wiki-links to them are kept");
+        Vec::new()
+    } else {
+        client.call(api::List {})?
+    };
+    for remote in remote {
+        let names = remote.title.iter().chain([&remote.slug]);
+        for name in names.map(|n| n.to_lowercase()) {
+            // local files win, unless they have not been created yet
+            let slug = slugs.entry(name).or_default();
+            if slug.is_none() {
+                *slug = Some(remote.slug.clone());
+            }
+        }
+    }
+
+    let mut unresolved = String::new();
+    let mut resolved = body.to_owned();
+    for link in found.iter().rev() {
+        let text = link.label.map_or_else(|| link.text(), str::to_owned);
+        let anchor = link.heading.map(|h| format!("#{}", preview::anchor(h)));
+
+        let markdown = if link.embed && link.is_attachment() {
+            format!("![{text}]({})", utf8_percent_encode(link.target, DEST))
+        } else if link.target.is_empty() {
+            format!("[{text}]({})", anchor.unwrap_or_default())
+        } else {
+            match slugs.get(&name(link.target).to_lowercase()) {
+                Some(Some(slug)) => {
+                    format!("[{text}](/blog/{slug}/{})", anchor.unwrap_or_default())
+                }
+                Some(None) => {
+                    let line = links::line_of(body, link.range.start);
+                    let _ = writeln!(
+                        unresolved,
+                        "  line {line}: {} (the post has not been created yet)",
+                        &body[link.range.clone()]
+                    );
+                    continue;
+                }
+                None if opts.dry_run => continue,
+                None => {
+                    let line = links::line_of(body, link.range.start);
+                    let _ = writeln!(unresolved, "  line {line}: {}", &body[link.range.clone()]);
+                    continue;
+                }
+            }
+        };
+
+        resolved.replace_range(link.range.clone(), &markdown);
+    }
+
+    if !unresolved.is_empty() {
+        bail!("Could not resolve wiki-links:\n{unresolved}");
+    }
+
+    post.body = Some(resolved);
+    Ok(())
+}
+
+/// The name of a note, without any folders or extension.
+fn name(target: &str) -> &str {
+    let name = target.rsplit('/').next().unwrap_or(target);
+    name.strip_suffix(".md").unwrap_or(name)
+}
+
+/// The slugs of the post files in `dir` that are linked to, by lowercase name.
+/// Files that have no slug yet are recorded as `None`.
+/// Only the files directly in `dir` are considered, not those in its subdirectories.
+fn local_slugs(dir: &Path, found: &[WikiLink<'_>]) -> Result<BTreeMap<String, Option<String>>> {
+    let names = found
+        .iter()
+        .map(|l| name(l.target).to_lowercase())
+        .collect::<Vec<_>>();
+
+    let mut slugs = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() || !watcher::is_post_file(&path) {
+            continue;
+        }
+        let Some(stem) = path.file_stem().map(|s| s.to_string_lossy().to_lowercase()) else {
+            continue;
+        };
+        if names.contains(&stem) {
+            // only the slug is used, so the dates need no time zone
+            let local = PostInput::from_file(&InputFile::File(path), None)?;
+            let _ = slugs.insert(stem, local.slug);
+        }
+    }
+    Ok(slugs)
+}
+
+/// Replaces the inline links in `body` that point to posts of `blog` with
+/// wiki-links, using the titles of `posts` as targets where possible.
+///
+/// Links whose label cannot be written as a wiki-link are left as they are.
+pub fn translate(body: &str, blog: &Blog, posts: &[Post]) -> String {
+    let titles = posts
+        .iter()
+        .filter_map(|p| Some((p.slug.as_str(), p.title.as_deref()?)))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut replacements = Vec::new();
+    for (event, range) in Parser::new_ext(body, preview::options()).into_offset_iter() {
+        let Event::Start(Tag::Link(LinkType::Inline, dest, _)) = event else {
+            continue;
+        };
+        let source = &body[range.clone()];
+        let Some(label) = source
+            .strip_prefix('[')
+            .and_then(|s| s.rfind("](").map(|end| &s[..end]))
+        else {
+            continue;
+        };
+        let Some(slug_range) = blog.slug_range(&dest) else {
+            continue;
+        };
+        let slug = &dest[slug_range.clone()];
+        let heading = match dest[slug_range.end..].trim_start_matches('/') {
+            "" => None,
+            rest => match rest.strip_prefix('#') {
+                Some(heading) => Some(heading),
+                None => continue,
+            },
+        };
+        if label.contains(['[', ']', '|', '\n']) {
+            continue;
+        }
+
+        let target = titles
+            .get(slug)
+            .filter(|t| !t.contains(['[', ']', '|', '#', '^']))
+            .map_or(slug, |t| *t);
+        let mut link = format!("[[{target}");
+        if let Some(heading) = heading {
+            let _ = write!(link, "#{heading}");
+        }
+        if label != target {
+            let _ = write!(link, "|{label}");
+        }
+        link.push_str("]]");
+        replacements.push((range, link));
+    }
+
+    let mut body = body.to_owned();
+    for (range, link) in replacements.into_iter().rev() {
+        body.replace_range(range, &link);
+    }
+    body
+}
+
+#[test]
+fn find_wiki_links() {
+    let body = concat!(
+        "See [[Other Note]], [[other-slug|the other]] and [[Note#Some Heading]].\n",
+        "![[image.png]] and `[[code]]`, [[#Local]]\n",
+    );
+    let links = find(body);
+    let targets = links.iter().map(|l| l.target).collect::<Vec<_>>();
+    assert_eq!(
+        targets,
+        ["Other Note", "other-slug", "Note", "image.png", ""]
+    );
+    assert_eq!(links[1].label, Some("the other"));
+    assert_eq!(links[2].text(), "Note > Some Heading");
+    assert!(links[3].embed && links[3].is_attachment());
+    assert_eq!(&body[links[3].range.clone()], "![[image.png]]");
+    assert_eq!(links[4].text(), "Local");
+}
+
+#[test]
+fn translate_to_wiki_links() {
+    let post = Post {
+        slug: String::from("other-note"),
+        title: Some(String::from("Other Note")),
+        body: None,
+        published_at: None,
+        url: Some(String::from("https://me.mataroa.blog/blog/other-note/")),
+    };
+    let blog = Blog::new(std::slice::from_ref(&post), []);
+
+    let body = concat!(
+        "See [Other Note](/blog/other-note/), [that](https://me.mataroa.blog/blog/other-note/#a-heading), ",
+        "[unknown](/blog/unknown/) and [ext](https://example.org/).",
+    );
+    assert_eq!(
+        translate(body, &blog, &[post]),
+        concat!(
+            "See [[Other Note]], [[Other Note#a-heading|that]], ",
+            "[[unknown]] and [ext](https://example.org/).",
+        )
+    );
+}